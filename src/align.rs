@@ -0,0 +1,88 @@
+use crate::Vertex;
+
+// Affine map between image coordinates, x' = m[0][0] * x + m[0][1] * y + m[0][2] and likewise for y'
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub m: [[f64; 3]; 2],
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform::scale(1.0, 1.0)
+    }
+
+    pub fn scale(sx: f64, sy: f64) -> Self {
+        Transform {
+            m: [[sx, 0.0, 0.0], [0.0, sy, 0.0]],
+        }
+    }
+
+    pub fn apply(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let m = &self.m;
+        [
+            m[0][0] * x + m[0][1] * y + m[0][2],
+            m[1][0] * x + m[1][1] * y + m[1][2],
+        ]
+    }
+
+    pub fn apply_line(&self, line: &[Vertex]) -> Vec<Vertex> {
+        line.iter()
+            .map(|v| Vertex {
+                position: self.apply(v.position),
+            })
+            .collect()
+    }
+
+    // Least squares rotation, uniform scale and shift taking the endpoints of each line
+    // onto those of its partner, or None while the points don't fix one
+    pub fn fit_lines(from: &[Vec<Vertex>], to: &[Vec<Vertex>]) -> Option<Transform> {
+        let points: Vec<([f64; 2], [f64; 2])> = from
+            .iter()
+            .zip(to.iter())
+            .flat_map(|(a, b)| a.iter().zip(b.iter()))
+            .map(|(a, b)| (a.position, b.position))
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        let (from_points, to_points): (Vec<[f64; 2]>, Vec<[f64; 2]>) =
+            points.iter().cloned().unzip();
+        let from_mean = mean(&from_points);
+        let to_mean = mean(&to_points);
+
+        // In complex numbers z' = s * z + t, with s = sum((z' - mean') * conj(z - mean)) / sum(|z - mean|^2)
+        let (mut re, mut im, mut norm) = (0.0, 0.0, 0.0);
+        for (a, b) in &points {
+            let (x, y) = (a[0] - from_mean[0], a[1] - from_mean[1]);
+            let (u, v) = (b[0] - to_mean[0], b[1] - to_mean[1]);
+            re += u * x + v * y;
+            im += v * x - u * y;
+            norm += x * x + y * y;
+        }
+        if norm < f64::EPSILON {
+            return None;
+        }
+        let (s_re, s_im) = (re / norm, im / norm);
+        Some(Transform {
+            m: [
+                [
+                    s_re,
+                    -s_im,
+                    to_mean[0] - (s_re * from_mean[0] - s_im * from_mean[1]),
+                ],
+                [
+                    s_im,
+                    s_re,
+                    to_mean[1] - (s_im * from_mean[0] + s_re * from_mean[1]),
+                ],
+            ],
+        })
+    }
+}
+
+fn mean(points: &[[f64; 2]]) -> [f64; 2] {
+    let sum = points
+        .iter()
+        .fold([0.0, 0.0], |acc, p| [acc[0] + p[0], acc[1] + p[1]]);
+    [sum[0] / points.len() as f64, sum[1] / points.len() as f64]
+}
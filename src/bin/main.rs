@@ -9,10 +9,15 @@ extern crate winit;
 
 use cgmath::{Matrix4, Vector2};
 use glium::glutin::{dpi, event, event_loop, window, ContextBuilder};
+use glium::uniforms::MagnifySamplerFilter;
 use glium::{index, texture, DrawParameters, IndexBuffer, Surface, VertexBuffer};
-use image::{ImageBuffer, RgbaImage};
+use image::RgbaImage;
+use imagemorph::view::Pane;
 use imagemorph::*;
+use std::io;
 use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
 use std::time::{Duration, Instant};
 
 // C++ code needed to pass to OpenGL
@@ -44,24 +49,1044 @@ const FRAGMENT_SHADER: &str = r#"
     in vec2 v_tex_coords;
     out vec4 color;
     uniform sampler2D tex;
+    uniform float opacity;
 
     void main() {
-      color = texture(tex, v_tex_coords);
+      vec4 texel = texture(tex, v_tex_coords);
+      color = vec4(texel.rgb, texel.a * opacity);
     }
 "#;
 
+const MAX_SUGGESTIONS: usize = 20;
+const SNAP_RADIUS: f64 = 12.0; // In window pixels
+const MIN_LINE_LENGTH: f64 = 5.0; // In window pixels
+const STROKE_TOLERANCE: f64 = 3.0; // In window pixels, how far a drawn stroke may stray from its corners
+const PICK_RADIUS: f64 = 8.0; // In window pixels
+const LABEL_HEIGHT: f64 = 10.0; // In window pixels
+const SLIDER_SPACE: f64 = 60.0; // In window pixels, below the live preview
+const SLIDER_MARGIN: f64 = 20.0; // In window pixels
+const ZOOM_STEP: f64 = 1.2; // Per mouse wheel notch
+const MIN_ZOOM: f64 = 0.5; // Relative to the image fitted to its column
+const MAX_ZOOM: f64 = 32.0; // Window pixels per image pixel
+const LOUPE_SIZE: f64 = 160.0; // In window pixels
+const LOUPE_ZOOM: f64 = 4.0; // Relative to the image under it
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const YELLOW: [f32; 4] = [1.0, 0.9, 0.1, 1.0];
+
 fn arg_error() -> ! {
-    eprintln!("Usage: cargo run image1 image2");
+    eprintln!("Usage: cargo run image1 image2 [t]");
+    eprintln!(
+        "t from 0 to 1 morphs between the images, -1 to 0 and 1 to 2 exaggerate their differences"
+    );
     std::process::exit(1);
 }
 
-fn ft_line_err() -> ! {
-    eprintln!("Must be same number of feature lines on each image! Exiting to start over");
-    std::process::exit(1);
+// An input image with everything the editor keeps for it
+struct LoadedImage {
+    pixels: RgbaImage,
+    texture: texture::SrgbTexture2d,
+    snapper: snap::EdgeSnapper,
+}
+
+impl LoadedImage {
+    fn open(display: &glium::Display, path: &str) -> Self {
+        let pixels = image::open(Path::new(path)).unwrap().to_rgba();
+        let dim = pixels.dimensions();
+        let raw = texture::RawImage2d::from_raw_rgba_reversed(&pixels.clone().into_raw(), dim);
+        LoadedImage {
+            texture: texture::SrgbTexture2d::new(display, raw).unwrap(),
+            snapper: snap::EdgeSnapper::new(&pixels),
+            pixels,
+        }
+    }
+
+    // Image position under the window position, moved onto a nearby edge when snapping is on
+    fn cursor(&self, pane: Pane, position: [f64; 2], snap_mode: bool) -> [f64; 2] {
+        let point = pane.to_image(position);
+        if !snap_mode {
+            return point;
+        }
+        match self
+            .snapper
+            .snap(point[0], point[1], SNAP_RADIUS / pane.scale)
+        {
+            Some((x, y)) => [x, y],
+            None => point,
+        }
+    }
+}
+
+struct ImagePair {
+    src: LoadedImage,
+    dst: LoadedImage,
+}
+
+impl ImagePair {
+    fn get(&self, side: Side) -> &LoadedImage {
+        match side {
+            Side::Src => &self.src,
+            Side::Dst => &self.dst,
+        }
+    }
+}
+
+// Morph parameters as t, p, a and b, one of which the arrow keys adjust
+#[derive(Copy, Clone, PartialEq)]
+struct MorphParams {
+    values: [f64; 4],
+    selected: usize,
+    line_mode: LineInterpolation,
+    blend_space: BlendSpace,
+}
+
+impl MorphParams {
+    const NAMES: [&'static str; 4] = ["t", "p", "a", "b"];
+    const STEPS: [f64; 4] = [0.05, 0.1, 0.1, 0.1];
+    // t goes past either image to exaggerate the differences between them
+    const LIMITS: [(f64, f64); 4] = [(-1.0, 2.0), (0.0, 2.0), (0.01, 10.0), (0.0, 4.0)];
+
+    fn tuple(&self) -> (f64, f64, f64, f64) {
+        let [t, p, a, b] = self.values;
+        (t, p, a, b)
+    }
+
+    fn set(&mut self, index: usize, value: f64) {
+        let (low, high) = Self::LIMITS[index];
+        self.values[index] = value.clamp(low, high);
+    }
+
+    fn adjust(&mut self, steps: f64) {
+        let index = self.selected;
+        self.set(index, self.values[index] + steps * Self::STEPS[index]);
+    }
+
+    fn select(&mut self, offset: usize) {
+        self.selected = (self.selected + offset) % self.values.len();
+    }
+
+    // K steps through the colour spaces, from the default linear light round to plain sRGB
+    fn next_blend_space(&mut self) {
+        self.blend_space = match self.blend_space {
+            BlendSpace::Linear => BlendSpace::Oklab,
+            BlendSpace::Oklab => BlendSpace::Lab,
+            BlendSpace::Lab => BlendSpace::Srgb,
+            BlendSpace::Srgb => BlendSpace::Linear,
+        };
+    }
+
+    fn describe(&self) -> String {
+        let values = Self::NAMES
+            .iter()
+            .zip(self.values.iter())
+            .enumerate()
+            .map(|(i, (name, value))| {
+                if i == self.selected {
+                    format!("[{} {:.2}]", name, value)
+                } else {
+                    format!("{} {:.2}", name, value)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let values = match self.line_mode {
+            LineInterpolation::Endpoints => values,
+            LineInterpolation::CentreAngleLength => format!("{} - turning lines", values),
+        };
+        match self.blend_space {
+            BlendSpace::Linear => values,
+            BlendSpace::Oklab => format!("{} - OKLab blend", values),
+            BlendSpace::Lab => format!("{} - CIELAB blend", values),
+            BlendSpace::Srgb => format!("{} - sRGB blend", values),
+        }
+    }
+}
+
+impl Default for MorphParams {
+    fn default() -> Self {
+        MorphParams {
+            values: [0.5, 1.0, 1.0, 1.0],
+            selected: 0,
+            line_mode: LineInterpolation::Endpoints,
+            blend_space: BlendSpace::default(),
+        }
+    }
+}
+
+// Source image on the left of the window and destination on the right,
+// with the live preview and its t slider between them when it is shown
+#[derive(Copy, Clone)]
+struct Layout {
+    src: Pane,
+    dst: Pane,
+    preview: Option<Pane>,
+    slider: ([f64; 2], [f64; 2]),
+    column: f64,
+    right: f64,
+    height: f64,
+}
+
+impl Layout {
+    fn new(images: &ImagePair, size: Vector2<f64>, show_preview: bool) -> Self {
+        let columns = if show_preview { 3.0 } else { 2.0 };
+        let column = [size.x / columns, size.y];
+        let right = size.x - column[0];
+        let slider_y = size.y - SLIDER_SPACE / 2.0;
+        Layout {
+            src: Pane::fit(images.src.pixels.dimensions(), [0.0, 0.0], column),
+            dst: Pane::fit(images.dst.pixels.dimensions(), [right, 0.0], column),
+            preview: if show_preview {
+                Some(Pane::fit(
+                    images.src.pixels.dimensions(),
+                    [column[0], 0.0],
+                    [column[0], size.y - SLIDER_SPACE],
+                ))
+            } else {
+                None
+            },
+            slider: (
+                [column[0] + SLIDER_MARGIN, slider_y],
+                [right - SLIDER_MARGIN, slider_y],
+            ),
+            column: column[0],
+            right,
+            height: size.y,
+        }
+    }
+
+    fn pane(&self, side: Side) -> Pane {
+        match side {
+            Side::Src => self.src,
+            Side::Dst => self.dst,
+        }
+    }
+
+    fn set_pane(&mut self, side: Side, pane: Pane) {
+        match side {
+            Side::Src => self.src = pane,
+            Side::Dst => self.dst = pane,
+        }
+    }
+
+    // Part of the window an image is drawn in, however far it is zoomed
+    fn column_rect(&self, side: Side) -> ([f64; 2], [f64; 2]) {
+        match side {
+            Side::Src => ([0.0, 0.0], [self.column, self.height]),
+            Side::Dst => ([self.right, 0.0], [self.right + self.column, self.height]),
+        }
+    }
+
+    // The whole image fitted into its column, as before any zooming
+    fn fitted(&self, side: Side) -> Pane {
+        let ([left, top], [right, bottom]) = self.column_rect(side);
+        Pane::fit(
+            self.pane(side).image_size,
+            [left, top],
+            [right - left, bottom - top],
+        )
+    }
+
+    // Zooms about a window position, from half the fitted size up to MAX_ZOOM
+    fn zoom(&mut self, side: Side, point: [f64; 2], factor: f64) {
+        let pane = self.pane(side);
+        let scale = (pane.scale * factor).clamp(self.fitted(side).scale * MIN_ZOOM, MAX_ZOOM);
+        self.set_pane(side, pane.zoom_at(point, scale / pane.scale));
+    }
+
+    // Same layout in a resized window, each image keeping its zoom and the point at its centre
+    fn resized(&self, images: &ImagePair, size: Vector2<f64>) -> Self {
+        let mut layout = Layout::new(images, size, self.preview.is_some());
+        for &side in &[Side::Src, Side::Dst] {
+            let centre = |([left, top], [right, bottom]): ([f64; 2], [f64; 2])| {
+                [(left + right) / 2.0, (top + bottom) / 2.0]
+            };
+            let old_centre = centre(self.column_rect(side));
+            let new_centre = centre(layout.column_rect(side));
+            let zoom = self.pane(side).scale / self.fitted(side).scale;
+            let pane = layout.pane(side).zoom_at(new_centre, zoom);
+            let [x, y] = pane.to_window(self.pane(side).to_image(old_centre));
+            layout.set_pane(side, pane.panned([new_centre[0] - x, new_centre[1] - y]));
+        }
+        layout
+    }
+
+    // Image being pointed at, None over the live preview
+    fn side_at(&self, [x, _]: [f64; 2]) -> Option<Side> {
+        if x < self.column {
+            Some(Side::Src)
+        } else if x >= self.right {
+            Some(Side::Dst)
+        } else {
+            None
+        }
+    }
+
+    fn on_slider(&self, [x, y]: [f64; 2]) -> bool {
+        self.side_at([x, y]).is_none() && (y - self.slider.0[1]).abs() < SLIDER_SPACE / 2.0
+    }
+
+    // Slider value under the window x position, from 0 at the left end to 1 at the right
+    fn slider_value(&self, x: f64) -> f64 {
+        let (start, end) = self.slider;
+        ((x - start[0]) / (end[0] - start[0])).clamp(0.0, 1.0)
+    }
+
+    // Knob position for a value, held at the ends when t is past either image
+    fn slider_position(&self, value: f64) -> [f64; 2] {
+        let (start, end) = self.slider;
+        let value = value.clamp(0.0, 1.0);
+        [start[0] + value * (end[0] - start[0]), start[1]]
+    }
+}
+
+// Maps window units, which are logical pixels from the top left corner, to the whole window
+fn projection(size: Vector2<f64>) -> [[f32; 4]; 4] {
+    let matrix: Matrix4<f32> = cgmath::ortho(0.0, size.x as f32, size.y as f32, 0.0, -1.0, 1.0);
+    matrix.into()
+}
+
+// Window title tells which image is being edited and the morph parameters
+fn set_title(display: &glium::Display, state: EditorState, params: &MorphParams) {
+    let title = match state {
+        EditorState::Editing(Side::Src) => "Image Morphing Tool - Source image",
+        EditorState::Editing(Side::Dst) => "Image Morphing Tool - Destination image",
+        EditorState::Preview(_) => "Image Morphing Tool - Preview",
+        EditorState::Rendering(_) => "Image Morphing Tool - Rendering... (Escape cancels)",
+    };
+    let title = format!("{} - {}", title, params.describe());
+    display.gl_window().window().set_title(&title);
+}
+
+fn scale_lines(lines: &[Vec<Vertex>], sx: f64, sy: f64) -> Vec<Vec<Vertex>> {
+    lines
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|v| Vertex {
+                    position: [v.position[0] * sx, v.position[1] * sy],
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Both images resized to one grid, as Morph needs, with their lines scaled to match
+fn morph_inputs(
+    src: &RgbaImage,
+    dst: &RgbaImage,
+    src_lines: &[Vec<Vertex>],
+    dst_lines: &[Vec<Vertex>],
+    (width, height): (u32, u32),
+) -> (RgbaImage, RgbaImage, LineSet, LineSet) {
+    let scaled = |img: &RgbaImage, lines: &[Vec<Vertex>]| {
+        let (w, h) = img.dimensions();
+        let lines = scale_lines(lines, width as f64 / w as f64, height as f64 / h as f64);
+        if (w, h) == (width, height) {
+            (img.clone(), lines)
+        } else {
+            let img = image::imageops::resize(img, width, height, image::Triangle);
+            (img, lines)
+        }
+    };
+    let (src, src_lines) = scaled(src, src_lines);
+    let (dst, dst_lines) = scaled(dst, dst_lines);
+    (src, dst, src_lines, dst_lines)
+}
+
+fn upload(display: &glium::Display, img: RgbaImage) -> texture::SrgbTexture2d {
+    let dim = img.dimensions();
+    let raw = texture::RawImage2d::from_raw_rgba_reversed(&img.into_raw(), dim);
+    texture::SrgbTexture2d::new(display, raw).unwrap()
+}
+
+// Morph of the lines drawn so far, run on the GPU every frame so it follows each edit
+struct LivePreview {
+    gpu: gpu::GpuMorph,
+    src: texture::Texture2d,
+    dst: texture::Texture2d,
+    scale: (f64, f64), // Destination image pixels to preview pixels
+}
+
+impl LivePreview {
+    fn new(display: &glium::Display, images: &ImagePair) -> Self {
+        let size = images.src.pixels.dimensions();
+        let (src, dst, _, _) = morph_inputs(&images.src.pixels, &images.dst.pixels, &[], &[], size);
+        let (dst_w, dst_h) = images.dst.pixels.dimensions();
+        LivePreview {
+            gpu: gpu::GpuMorph::new(display).unwrap(),
+            src: gpu::GpuMorph::texture(display, &src),
+            dst: gpu::GpuMorph::texture(display, &dst),
+            scale: (size.0 as f64 / dst_w as f64, size.1 as f64 / dst_h as f64),
+        }
+    }
+
+    // Pairs drawn on both images split into straight lines, with a dragged endpoint already where the cursor is,
+    // and the timing of each straight line
+    fn lines(
+        edit_tool: &EditTool,
+        lines: &FeatureLines,
+        editing: Option<Side>,
+        cursor: [f64; 2],
+    ) -> (LineSet, LineSet, Vec<LineTiming>) {
+        let displayed = |side: Side| {
+            let drag_cursor = editing.filter(|&active| active == side).map(|_| cursor);
+            edit_tool.displayed(lines, side, drag_cursor)
+        };
+        let dst_lines = displayed(Side::Dst);
+        let mut timings = Vec::new();
+        let (src_lines, dst_lines): (LineSet, LineSet) = displayed(Side::Src)
+            .into_iter()
+            .filter_map(|(index, src_line)| {
+                let (_, dst_line) = dst_lines.iter().find(|(i, _)| *i == index)?;
+                timings.push(lines.pairs()[index].timing.unwrap_or_default());
+                Some((src_line, dst_line.clone()))
+            })
+            .unzip();
+        let timings = curves::split_timings(&src_lines, &dst_lines, &timings);
+        let (src_lines, dst_lines) = curves::split_lines(&src_lines, &dst_lines);
+        (src_lines, dst_lines, timings)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &mut self,
+        target: &mut glium::Frame,
+        display: &glium::Display,
+        pane: Pane,
+        size: Vector2<f64>,
+        src_lines: &[Vec<Vertex>],
+        dst_lines: &[Vec<Vertex>],
+        timings: &[LineTiming],
+        params: MorphParams,
+    ) {
+        let viewport = framebuffer_rect(target, size, pane.rect());
+        let dst_lines = scale_lines(dst_lines, self.scale.0, self.scale.1);
+        self.gpu.line_mode = params.line_mode;
+        self.gpu.blend_space = params.blend_space;
+        self.gpu.timings = timings.to_vec();
+        self.gpu.draw(
+            display,
+            target,
+            viewport,
+            &self.src,
+            &self.dst,
+            src_lines,
+            &dst_lines,
+            params.tuple(),
+        );
+    }
+}
+
+// The other image drawn see-through over the one being edited, with its lines as ghosts
+struct OnionSkin {
+    visible: bool,
+    ghosts: bool,
+    aligned: bool, // Lined up using the pairs drawn so far rather than just stretched to fit
+    opacity: f64,
+}
+
+impl Default for OnionSkin {
+    fn default() -> Self {
+        OnionSkin {
+            visible: false,
+            ghosts: false,
+            aligned: true,
+            opacity: 0.5,
+        }
+    }
+}
+
+impl OnionSkin {
+    // Maps image pixels of the other image to those of the one being edited
+    fn transform(&self, images: &ImagePair, lines: &FeatureLines, side: Side) -> align::Transform {
+        let (src_lines, dst_lines) = lines.matched_lines();
+        let (from, to) = match side {
+            Side::Src => (dst_lines, src_lines),
+            Side::Dst => (src_lines, dst_lines),
+        };
+        let fitted = if self.aligned {
+            align::Transform::fit_lines(&from, &to)
+        } else {
+            None
+        };
+        fitted.unwrap_or_else(|| {
+            let (w, h) = images.get(side).pixels.dimensions();
+            let (other_w, other_h) = images.get(side.other()).pixels.dimensions();
+            align::Transform::scale(w as f64 / other_w as f64, h as f64 / other_h as f64)
+        })
+    }
+}
+
+enum RenderMessage {
+    Progress(f64),
+    Finished(RgbaImage, io::Result<()>), // The morph and whether saving it to disk worked
+}
+
+// Full resolution morph running on its own thread, so the window keeps drawing while it works
+struct RenderJob {
+    receiver: mpsc::Receiver<RenderMessage>,
+    cancel: CancelToken,
+    progress: f64, // Fraction of the work done, from 0 to 1
+}
+
+impl RenderJob {
+    #[allow(clippy::too_many_arguments)]
+    fn start(
+        src: RgbaImage,
+        dst: RgbaImage,
+        src_lines: LineSet,
+        dst_lines: LineSet,
+        timings: Vec<LineTiming>,
+        params: (f64, f64, f64, f64),
+        line_mode: LineInterpolation,
+        blend_space: BlendSpace,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = CancelToken::new();
+        let worker_cancel = cancel.clone();
+        thread::spawn(move || {
+            let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, params)
+                .with_line_mode(line_mode)
+                .with_blend_space(blend_space)
+                .with_timings(&timings);
+            // Sends fail once the job has been dropped, which only happens after cancelling it
+            let mut observer = |progress: Progress| {
+                let _ = sender.send(RenderMessage::Progress(progress.fraction()));
+            };
+            if let Ok(morphed) = morph.morph_with(&mut observer, &worker_cancel) {
+                let saved = morphed.save("morphed.png");
+                let _ = sender.send(RenderMessage::Finished(morphed, saved));
+            }
+        });
+        RenderJob {
+            receiver,
+            cancel,
+            progress: 0.0,
+        }
+    }
+
+    fn cancel(self) {
+        self.cancel.cancel();
+    }
+
+    // Takes in what the worker has sent since the last call, the morph and the result of saving
+    // it once it is done
+    fn poll(&mut self) -> Option<(RgbaImage, io::Result<()>)> {
+        for message in self.receiver.try_iter() {
+            match message {
+                RenderMessage::Progress(progress) => self.progress = progress,
+                RenderMessage::Finished(morphed, saved) => return Some((morphed, saved)),
+            }
+        }
+        None
+    }
+}
+
+// Evenly spread hues so neighbouring pair numbers get clearly different colours
+fn pair_color(index: usize) -> [f32; 4] {
+    let hue = (index as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [0.25 + 0.75 * r, 0.25 + 0.75 * g, 0.25 + 0.75 * b, 1.0]
+}
+
+fn quad(([left, top], [right, bottom]): ([f64; 2], [f64; 2])) -> Vec<Vertex> {
+    vec![
+        Vertex {
+            position: [left, top],
+        },
+        Vertex {
+            position: [right, top],
+        },
+        Vertex {
+            position: [left, bottom],
+        },
+        Vertex {
+            position: [right, bottom],
+        },
+    ]
+}
+
+// Small square drawn around a window position
+fn marker([x, y]: [f64; 2]) -> Vec<Vertex> {
+    let r = 4.0;
+    [[-r, -r], [r, -r], [r, r], [-r, r], [-r, -r]]
+        .iter()
+        .map(|[dx, dy]| Vertex {
+            position: [x + dx, y + dy],
+        })
+        .collect()
+}
+
+// Seven segment strokes for a number with its top left at (x, y), so labels need no font
+fn number_strokes(number: usize, [x, y]: [f64; 2], height: f64) -> Vec<Vec<Vertex>> {
+    // Segments a to g as bits, a is the top bar and g the middle one
+    const DIGITS: [u8; 10] = [
+        0b011_1111, 0b000_0110, 0b101_1011, 0b100_1111, 0b110_0110, 0b110_1101, 0b111_1101,
+        0b000_0111, 0b111_1111, 0b110_1111,
+    ];
+    let w = height / 2.0;
+    let h = height / 2.0;
+    let segments = [
+        [[0.0, 0.0], [w, 0.0]],
+        [[w, 0.0], [w, h]],
+        [[w, h], [w, 2.0 * h]],
+        [[0.0, 2.0 * h], [w, 2.0 * h]],
+        [[0.0, h], [0.0, 2.0 * h]],
+        [[0.0, 0.0], [0.0, h]],
+        [[0.0, h], [w, h]],
+    ];
+    let mut strokes = Vec::new();
+    for (i, digit) in number.to_string().bytes().enumerate() {
+        let left = x + i as f64 * (w + height / 4.0);
+        for (bit, [start, end]) in segments.iter().enumerate() {
+            if DIGITS[(digit - b'0') as usize] & (1 << bit) != 0 {
+                strokes.push(vec![
+                    Vertex {
+                        position: [left + start[0], y + start[1]],
+                    },
+                    Vertex {
+                        position: [left + end[0], y + end[1]],
+                    },
+                ]);
+            }
+        }
+    }
+    strokes
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+enum StrokeKind {
+    #[default]
+    Line,
+    Polyline,
+    Curve,
+}
+
+impl StrokeKind {
+    fn next(self) -> StrokeKind {
+        match self {
+            StrokeKind::Line => StrokeKind::Polyline,
+            StrokeKind::Polyline => StrokeKind::Curve,
+            StrokeKind::Curve => StrokeKind::Line,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            StrokeKind::Line => "straight lines",
+            StrokeKind::Polyline => "polylines",
+            StrokeKind::Curve => "curves",
+        }
+    }
+}
+
+// Press starts a feature at the cursor, dragging draws it and release finishes it.
+// Lines run straight to the release point while polylines and curves follow the path dragged
+#[derive(Default)]
+struct LineTool {
+    kind: StrokeKind,
+    path: Vec<[f64; 2]>,
+}
+
+impl LineTool {
+    fn press(&mut self, cursor: [f64; 2]) {
+        self.path = vec![cursor];
+    }
+
+    fn is_drawing(&self) -> bool {
+        !self.path.is_empty()
+    }
+
+    fn follow(&mut self, cursor: [f64; 2]) {
+        if self.kind != StrokeKind::Line && self.path.last().map_or(false, |&last| last != cursor) {
+            self.path.push(cursor);
+        }
+    }
+
+    // Finished feature, or None if nothing was being drawn or it is too short to warp with.
+    // The dragged path is simplified to corners within tolerance, then smoothed for curves
+    fn release(
+        &mut self,
+        cursor: [f64; 2],
+        min_length: f64,
+        tolerance: f64,
+    ) -> Option<Vec<Vertex>> {
+        let mut path = std::mem::take(&mut self.path);
+        let start = *path.first()?;
+        path.push(cursor);
+        let points = match self.kind {
+            StrokeKind::Line => vec![start, cursor],
+            StrokeKind::Polyline => curves::simplify(&path, tolerance),
+            StrokeKind::Curve => {
+                let knots = curves::simplify(&path, tolerance);
+                curves::simplify(&curves::bezier(&curves::smooth(&knots), 8), tolerance / 4.0)
+            }
+        };
+        let length: f64 = points
+            .windows(2)
+            .map(|pair| (pair[1][0] - pair[0][0]).hypot(pair[1][1] - pair[0][1]))
+            .sum();
+        if length < min_length {
+            return None;
+        }
+        Some(
+            points
+                .into_iter()
+                .map(|position| Vertex { position })
+                .collect(),
+        )
+    }
+
+    fn cancel(&mut self) {
+        self.path.clear();
+    }
+
+    // Rubber band from the press point, along the path dragged so far, to the cursor
+    fn preview(&self, cursor: [f64; 2]) -> Option<Vec<Vertex>> {
+        let start = *self.path.first()?;
+        let path = match self.kind {
+            StrokeKind::Line => vec![start, cursor],
+            _ => self.path.iter().cloned().chain(Some(cursor)).collect(),
+        };
+        Some(
+            path.into_iter()
+                .map(|position| Vertex { position })
+                .collect(),
+        )
+    }
+}
+
+// Selects lines and drags their endpoints while edit mode is on
+#[derive(Default)]
+struct EditTool {
+    active: bool,
+    selected: Option<usize>,
+    dragging: Option<(usize, usize)>,
+}
+
+impl EditTool {
+    fn clear(&mut self) {
+        self.selected = None;
+        self.dragging = None;
+    }
+
+    fn press(&mut self, lines: &FeatureLines, side: Side, cursor: [f64; 2], radius: f64) {
+        match lines.pick_endpoint(side, cursor, radius) {
+            Some((index, endpoint)) => {
+                self.selected = Some(index);
+                self.dragging = Some((index, endpoint));
+            }
+            None => self.selected = lines.pick_line(side, cursor, radius),
+        }
+    }
+
+    fn release(&mut self, lines: &mut FeatureLines, side: Side, cursor: [f64; 2]) {
+        if let Some((index, endpoint)) = self.dragging.take() {
+            lines.move_endpoint(side, index, endpoint, cursor);
+        }
+    }
+
+    // Lines of one image as they should be drawn, with a dragged endpoint following the cursor
+    fn displayed(
+        &self,
+        lines: &FeatureLines,
+        side: Side,
+        cursor: Option<[f64; 2]>,
+    ) -> Vec<(usize, Vec<Vertex>)> {
+        lines
+            .lines(side)
+            .into_iter()
+            .map(|(index, line)| {
+                let mut line = line.clone();
+                if let (Some((dragged, endpoint)), Some(cursor)) = (self.dragging, cursor) {
+                    if dragged == index {
+                        line[endpoint].position = cursor;
+                    }
+                }
+                (index, line)
+            })
+            .collect()
+    }
+}
+
+// Editing keys shared by both images, returns false for keys it doesn't use
+fn handle_edit_key(
+    key: event::VirtualKeyCode,
+    ctrl: bool,
+    lines: &mut FeatureLines,
+    edit_tool: &mut EditTool,
+) -> bool {
+    match key {
+        event::VirtualKeyCode::Tab => {
+            edit_tool.active = !edit_tool.active;
+            edit_tool.clear();
+            println!("Edit mode {}", if edit_tool.active { "on" } else { "off" });
+        }
+        event::VirtualKeyCode::Delete => {
+            if let Some(index) = edit_tool.selected.take() {
+                lines.delete(index);
+                println!("Deleted feature line {} from both images", index + 1);
+            }
+        }
+        // Cycles the selected line between following t, moving in the first half and moving in
+        // the second half, so features can change one after another
+        event::VirtualKeyCode::T => {
+            let selected = edit_tool
+                .selected
+                .and_then(|index| Some((index, lines.pairs().get(index)?.timing)));
+            if let Some((index, current)) = selected {
+                let (timing, when) = match current {
+                    None => (
+                        Some(LineTiming::new(0.0, 0.5, Easing::Linear)),
+                        "in the first half",
+                    ),
+                    Some(timing) if timing.start == 0.0 => (
+                        Some(LineTiming::new(0.5, 1.0, Easing::Linear)),
+                        "in the second half",
+                    ),
+                    Some(_) => (None, "with t"),
+                };
+                lines.set_timing(index, timing);
+                println!("Feature line {} moves {}", index + 1, when);
+            }
+        }
+        event::VirtualKeyCode::Z if ctrl => {
+            if lines.undo() {
+                edit_tool.clear();
+                println!("Undo");
+            }
+        }
+        event::VirtualKeyCode::Y if ctrl => {
+            if lines.redo() {
+                edit_tool.clear();
+                println!("Redo");
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+// Draws a texture over the window rectangle of a pane
+struct ImagePainter {
+    program: glium::Program,
+    vertices: VertexBuffer<Vertex>,
+    indices: IndexBuffer<u16>,
+    projection: [[f32; 4]; 4],
+}
+
+impl ImagePainter {
+    fn draw(
+        &self,
+        target: &mut glium::Frame,
+        pane: Pane,
+        texture: &texture::SrgbTexture2d,
+        clip: Option<glium::Rect>,
+    ) {
+        let corners = quad(pane.rect());
+        let corners = [
+            corners[0].position,
+            corners[1].position,
+            corners[2].position,
+            corners[3].position,
+        ];
+        self.draw_quad(target, corners, texture, clip, 1.0);
+    }
+
+    // Texture stretched over any four window corners, in the order top left, top right,
+    // bottom left and bottom right of the image
+    fn draw_quad(
+        &self,
+        target: &mut glium::Frame,
+        corners: [[f64; 2]; 4],
+        texture: &texture::SrgbTexture2d,
+        clip: Option<glium::Rect>,
+        opacity: f32,
+    ) {
+        let corners: Vec<Vertex> = corners
+            .iter()
+            .map(|&position| Vertex { position })
+            .collect();
+        self.vertices.write(&corners);
+        // Zoomed in pixels stay sharp squares, so their edges can be placed exactly
+        let uniforms = uniform! {
+          projection: self.projection,
+          tex: texture.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
+          opacity: opacity,
+        };
+        let params = DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            scissor: clip,
+            ..Default::default()
+        };
+        target
+            .draw(
+                &self.vertices,
+                &self.indices,
+                &self.program,
+                &uniforms,
+                &params,
+            )
+            .unwrap();
+    }
+}
+
+// Window rectangle in framebuffer pixels, which count up from the bottom, as scissors and viewports need
+fn framebuffer_rect(
+    target: &glium::Frame,
+    size: Vector2<f64>,
+    ([left, top], [right, bottom]): ([f64; 2], [f64; 2]),
+) -> glium::Rect {
+    let (width, height) = target.get_dimensions();
+    let (sx, sy) = (width as f64 / size.x, height as f64 / size.y);
+    let left = (left * sx).round().clamp(0.0, width as f64);
+    let right = (right * sx).round().clamp(left, width as f64);
+    let low = ((size.y - bottom) * sy).round().clamp(0.0, height as f64);
+    let high = ((size.y - top) * sy).round().clamp(low, height as f64);
+    glium::Rect {
+        left: left as u32,
+        bottom: low as u32,
+        width: (right - left) as u32,
+        height: (high - low) as u32,
+    }
+}
+
+// Draws antialiased lines and markers given in window coordinates
+struct LinePainter {
+    strokes: strokes::StrokePainter,
+    projection: [[f32; 4]; 4],
+    clip: Option<glium::Rect>, // Scissor rectangle, if drawing is limited to part of the window
+}
+
+impl LinePainter {
+    fn draw_strokes(
+        &self,
+        target: &mut glium::Frame,
+        display: &glium::Display,
+        strokes: &strokes::Strokes,
+    ) {
+        self.strokes
+            .draw(display, target, strokes, self.projection, self.clip);
+    }
+
+    fn draw(
+        &self,
+        target: &mut glium::Frame,
+        display: &glium::Display,
+        line: &[Vertex],
+        color: [f32; 4],
+        width: f32,
+    ) {
+        let mut strokes = strokes::Strokes::new();
+        let points: Vec<[f64; 2]> = line.iter().map(|v| v.position).collect();
+        strokes.polyline(&points, width as f64, color);
+        self.draw_strokes(target, display, &strokes);
+    }
+
+    // Line given in image pixels of the pane
+    fn draw_in(
+        &self,
+        target: &mut glium::Frame,
+        display: &glium::Display,
+        pane: Pane,
+        line: &[Vertex],
+        color: [f32; 4],
+        width: f32,
+    ) {
+        let line: Vec<Vertex> = line
+            .iter()
+            .map(|v| Vertex {
+                position: pane.to_window(v.position),
+            })
+            .collect();
+        self.draw(target, display, &line, color, width);
+    }
+}
+
+// Short step along a polyline centred halfway along its length, where its arrow is drawn
+fn halfway(points: &[[f64; 2]]) -> ([f64; 2], [f64; 2]) {
+    let length = |pair: &[[f64; 2]]| (pair[1][0] - pair[0][0]).hypot(pair[1][1] - pair[0][1]);
+    let mut remaining = points.windows(2).map(length).sum::<f64>() / 2.0;
+    for pair in points.windows(2) {
+        let segment = length(pair);
+        if segment > 0.0 && (remaining <= segment || pair[1] == points[points.len() - 1]) {
+            let dir = [
+                (pair[1][0] - pair[0][0]) / segment,
+                (pair[1][1] - pair[0][1]) / segment,
+            ];
+            let mid = [
+                pair[0][0] + dir[0] * remaining,
+                pair[0][1] + dir[1] * remaining,
+            ];
+            return (
+                [mid[0] - dir[0], mid[1] - dir[1]],
+                [mid[0] + dir[0], mid[1] + dir[1]],
+            );
+        }
+        remaining -= segment;
+    }
+    (points[0], points[points.len() - 1])
+}
+
+// Feature lines of one image in their pair colours and numbered, the selected one thicker.
+// Each has a dot at both ends and an arrow halfway along towards its end, since swapping the ends
+// mirrors the warp
+#[allow(clippy::too_many_arguments)]
+fn draw_feature_lines(
+    target: &mut glium::Frame,
+    display: &glium::Display,
+    painter: &LinePainter,
+    pane: Pane,
+    lines: &FeatureLines,
+    side: Side,
+    edit_tool: &EditTool,
+    cursor: Option<[f64; 2]>,
+    highlight: Option<usize>,
+) {
+    let mut batch = strokes::Strokes::new();
+    for (index, line) in edit_tool.displayed(lines, side, cursor) {
+        let color = pair_color(index);
+        let points: Vec<[f64; 2]> = line.iter().map(|v| pane.to_window(v.position)).collect();
+        let (start, end) = (points[0], points[points.len() - 1]);
+        let selected = edit_tool.selected == Some(index);
+        let width = if selected { 5.0 } else { 2.5 };
+        if highlight == Some(index) {
+            batch.polyline(&points, width + 5.0, WHITE);
+        }
+        batch.polyline(&points, width, color);
+        batch.dot(start, width / 2.0 + 2.5, color);
+        batch.dot(end, width / 2.0 + 2.5, color);
+        let (a, b) = halfway(&points);
+        batch.arrow(a, b, 4.0 + 2.0 * width, width * 0.8, color);
+        if selected {
+            for &corner in &points {
+                batch.polyline(
+                    &marker(corner)
+                        .iter()
+                        .map(|v| v.position)
+                        .collect::<Vec<_>>(),
+                    2.0,
+                    WHITE,
+                );
+            }
+        }
+        let label = [start[0] + 6.0, start[1] - LABEL_HEIGHT - 6.0];
+        for stroke in number_strokes(index + 1, label, LABEL_HEIGHT) {
+            batch.segment(stroke[0].position, stroke[1].position, 1.5, color);
+        }
+    }
+    painter.draw_strokes(target, display, &batch);
 }
 
 fn main() {
-   /* let src_line = vec![
+    /* let src_line = vec![
         Vertex {
             position: [541f64, 160f64],
         },
@@ -114,8 +1139,8 @@ fn main() {
     let dst_lines = vec![dst_line, dst_line2, dst_line3];
     let src_path = std::env::args().nth(1).unwrap();
     let dst_path = std::env::args().nth(2).unwrap();
-    let src = image::open(&Path::new(&src_path)).unwrap().to_rgba();
-    let dst = image::open(&Path::new(&dst_path)).unwrap();
+    let src = image::open(Path::new(&src_path)).unwrap().to_rgba();
+    let dst = image::open(Path::new(&dst_path)).unwrap();
     let (w, h) = src.dimensions();
     let dst = dst.resize_exact(w, h, image::imageops::FilterType::Nearest).to_rgba();
     let morph = Morph::new(
@@ -131,11 +1156,20 @@ fn main() {
     let morphed: RgbaImage = morph.morph();
     let image = image::DynamicImage::ImageRgba8(morphed).flipv();
     image.save("morphed.png").unwrap();*/
-    if std::env::args().count() != 3 {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 && args.len() != 4 {
         arg_error();
     }
-    let src_path = std::env::args().nth(1).unwrap();
-    let dst_path = std::env::args().nth(2).unwrap();
+    let src_path = args[1].clone();
+    let dst_path = args[2].clone();
+    let mut params = MorphParams::default();
+    if let Some(t) = args.get(3) {
+        let (low, high) = MorphParams::LIMITS[0];
+        match t.parse::<f64>() {
+            Ok(t) if (low..=high).contains(&t) => params.set(0, t),
+            _ => arg_error(),
+        }
+    }
 
     // Boilerplate code for initilizing glium display window
     // Adapted for use from tutorial at docs.rs/glium/0.26.0/glium
@@ -144,105 +1178,368 @@ fn main() {
         .with_inner_size(dpi::LogicalSize::new(1024.0, 768.0))
         .with_title("Image Morphing Tool");
     let cb = ContextBuilder::new();
-    let display_src = glium::Display::new(wb, cb, &events_loop).unwrap();
-    let mut src_img = image::open(&Path::new(&src_path)).unwrap().to_rgba();
-
-    let src = {
-        let src_img = image::open(&Path::new(&src_path)).unwrap().to_rgba();
-        let src_dim = src_img.dimensions();
-        let src_img = texture::RawImage2d::from_raw_rgba_reversed(&src_img.into_raw(), src_dim);
-        texture::SrgbTexture2d::new(&display_src, src_img).unwrap()
+    let display = glium::Display::new(wb, cb, &events_loop).unwrap();
+    let images = ImagePair {
+        src: LoadedImage::open(&display, &src_path),
+        dst: LoadedImage::open(&display, &dst_path),
     };
+    let mut result: Option<texture::SrgbTexture2d> = None;
 
     let (vertices, indices) = {
         let data: Vec<u16> = vec![0, 1, 2, 1, 3, 2];
-        let vertex_buf = VertexBuffer::empty_dynamic(&display_src, 4).unwrap();
+        let vertex_buf = VertexBuffer::empty_dynamic(&display, 4).unwrap();
         let index_buf =
-            IndexBuffer::new(&display_src, index::PrimitiveType::TrianglesList, &data).unwrap();
+            IndexBuffer::new(&display, index::PrimitiveType::TrianglesList, &data).unwrap();
         (vertex_buf, index_buf)
     };
 
     let program =
-        glium::Program::from_source(&display_src, VERTEX_SHADER, FRAGMENT_SHADER, None).unwrap();
+        glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER, None).unwrap();
+
+    // Window size in logical pixels, physical pixels and their ratio, all kept up to date
+    let mut scale_factor = display.gl_window().window().scale_factor();
+    let mut window_size = display.gl_window().window().inner_size();
+    let logical: dpi::LogicalSize<f64> = window_size.to_logical(scale_factor);
+    let mut size = Vector2 {
+        x: logical.width,
+        y: logical.height,
+    };
+    let perspective = projection(size);
 
-    let perspective = {
-        let matrix: Matrix4<f32> = cgmath::ortho(0.0, 1024.0, 768.0, 0.0, -1.0, 1.0);
-        Into::<[[f32; 4]; 4]>::into(matrix)
+    let mut image_painter = ImagePainter {
+        program,
+        vertices,
+        indices,
+        projection: perspective,
     };
 
-    let size = Vector2 {
-        x: 1024.0,
-        y: 768.0,
+    let mut painter = LinePainter {
+        strokes: strokes::StrokePainter::new(&display).unwrap(),
+        projection: perspective,
+        clip: None,
     };
-    let position = Vector2 { x: 512.0, y: 384.0 };
+
+    let mut show_preview = true;
+    let mut layout = Layout::new(&images, size, show_preview);
+    let mut live_preview = LivePreview::new(&display, &images);
+    let mut scrubbing = false; // Dragging the t slider
+    let mut panning: Option<(Side, [f64; 2])> = None; // Image being dragged and the last cursor position
+    let mut loupe = false;
+    let mut onion = OnionSkin::default();
+    let mut render: Option<RenderJob> = None;
     let mut x_pos: f64 = 0.0;
     let mut y_pos: f64 = 0.0;
-    let mut is_src = 1;
-    let mut line_seg_pt = 0;
-    let mut src_lines: Vec<VertexBuffer<Vertex>> = Vec::new();
-    let mut src_lines_ref: Vec<Vec<Vertex>> = Vec::new();
-    let line_idx = index::NoIndices(index::PrimitiveType::LineStrip);
-    let line_params = DrawParameters {
-        line_width: Some(2.0),
-        ..Default::default()
-    };
-    let mut new_line: Vec<Vertex> = Vec::new();
+    let mut state = EditorState::default();
+    let mut lines = FeatureLines::new();
+    let mut line_tool = LineTool::default();
+    let mut edit_tool = EditTool::default();
+    let mut ctrl = false;
+    let mut suggestions: Vec<correspondence::Suggestion> = Vec::new();
+    let mut snap_mode = false;
+    let mut cursor: [f64; 2] = [0.0, 0.0]; // In image pixels of the image being edited
+    set_title(&display, state, &params);
 
     events_loop.run(move |event, _, control_flow| {
-        let next_frame_time = Instant::now() + Duration::from_micros(1);
+        let next_frame_time = Instant::now() + Duration::from_nanos(16_666_667);
         *control_flow = event_loop::ControlFlow::WaitUntil(next_frame_time);
-        if line_seg_pt == 2 {
-            new_line.clear();
-            line_seg_pt = 0;
-        }
+        let prev_state = state;
+        let prev_params = params;
 
         match event {
             event::Event::WindowEvent { event, .. } => match event {
                 event::WindowEvent::CloseRequested => {
-                    if is_src == 1 {
-                        is_src = 0;
-                    } else {
-                        return;
-                    };
+                    *control_flow = event_loop::ControlFlow::Exit;
+                    return;
                 }
-                // Tracks position of cursor
+                // Tracks position of cursor, editing whichever image it is over
                 event::WindowEvent::CursorMoved {
                     position: physical_position,
                     ..
                 } => {
-                    x_pos = physical_position.x;
-                    y_pos = physical_position.y;
+                    let position: dpi::LogicalPosition<f64> =
+                        physical_position.to_logical(scale_factor);
+                    x_pos = position.x;
+                    y_pos = position.y;
+                    if let Some((side, [last_x, last_y])) = panning {
+                        let pane = layout.pane(side).panned([x_pos - last_x, y_pos - last_y]);
+                        layout.set_pane(side, pane);
+                        panning = Some((side, [x_pos, y_pos]));
+                    } else if scrubbing {
+                        params.set(0, layout.slider_value(x_pos));
+                    } else if let Some(side) = state.editing() {
+                        let busy = line_tool.is_drawing() || edit_tool.dragging.is_some();
+                        let over = layout.side_at([x_pos, y_pos]);
+                        if over.map_or(false, |over| over != side) && !busy {
+                            edit_tool.clear();
+                            state = state.next(EditorEvent::SwitchImage);
+                        }
+                    }
+                }
+                event::WindowEvent::Resized(physical_size) => window_size = physical_size,
+                event::WindowEvent::ScaleFactorChanged {
+                    scale_factor: new_factor,
+                    new_inner_size,
+                } => {
+                    scale_factor = new_factor;
+                    window_size = *new_inner_size;
+                }
+                // The wheel zooms the image under the cursor about the cursor
+                event::WindowEvent::MouseWheel { delta, .. } => {
+                    let notches = match delta {
+                        event::MouseScrollDelta::LineDelta(_, y) => y as f64,
+                        event::MouseScrollDelta::PixelDelta(position) => position.y / 40.0,
+                    };
+                    match (state.editing(), layout.side_at([x_pos, y_pos])) {
+                        (Some(_), Some(side)) => {
+                            layout.zoom(side, [x_pos, y_pos], ZOOM_STEP.powf(notches))
+                        }
+                        _ => return,
+                    }
                 }
-                event::WindowEvent::MouseInput { .. } => {
-                    new_line.push(Vertex {
-                        position: [x_pos, y_pos],
-                    });
-                    if line_seg_pt == 0 {
-                        line_seg_pt = 1;
+                // Dragging with the middle button pans the image under the cursor
+                event::WindowEvent::MouseInput {
+                    state: button_state,
+                    button: event::MouseButton::Middle,
+                    ..
+                } => {
+                    panning = match (button_state, state.editing()) {
+                        (event::ElementState::Pressed, Some(_)) => layout
+                            .side_at([x_pos, y_pos])
+                            .map(|side| (side, [x_pos, y_pos])),
+                        _ => None,
+                    };
+                    return;
+                }
+                event::WindowEvent::MouseInput {
+                    state: button_state,
+                    button,
+                    ..
+                } => {
+                    let side = match state.editing() {
+                        Some(side) => side,
+                        None => return,
+                    };
+                    // Clicks over the live preview only move the t slider
+                    let busy = line_tool.is_drawing() || edit_tool.dragging.is_some();
+                    if scrubbing || (layout.side_at([x_pos, y_pos]).is_none() && !busy) {
+                        scrubbing = button_state == event::ElementState::Pressed
+                            && button == event::MouseButton::Left
+                            && layout.on_slider([x_pos, y_pos]);
+                        if scrubbing {
+                            params.set(0, layout.slider_value(x_pos));
+                        }
                     } else {
-                        if src_lines.is_empty() {
-                            let image: texture::RawImage2d<u8> =
-                                display_src.read_front_buffer().unwrap();
-                            let image: image::RgbaImage = image::ImageBuffer::from_raw(
-                                image.width,
-                                image.height,
-                                image.data.into_owned(),
-                            )
-                            .unwrap();
-                            src_img = image;
+                        let scale = layout.pane(side).scale;
+                        match (button_state, button) {
+                            (event::ElementState::Pressed, event::MouseButton::Left)
+                                if edit_tool.active =>
+                            {
+                                edit_tool.press(&lines, side, cursor, PICK_RADIUS / scale);
+                            }
+                            (event::ElementState::Released, event::MouseButton::Left)
+                                if edit_tool.active =>
+                            {
+                                edit_tool.release(&mut lines, side, cursor);
+                            }
+                            (event::ElementState::Pressed, event::MouseButton::Left) => {
+                                line_tool.press(cursor);
+                            }
+                            (event::ElementState::Released, event::MouseButton::Left)
+                                if line_tool.is_drawing() =>
+                            {
+                                let new_line =
+                                    match line_tool.release(
+                                        cursor,
+                                        MIN_LINE_LENGTH / scale,
+                                        STROKE_TOLERANCE / scale,
+                                    ) {
+                                        Some(line) => line,
+                                        None => {
+                                            println!("Feature line too short, ignored");
+                                            return;
+                                        }
+                                    };
+                                let end = new_line[new_line.len() - 1].position;
+                                println!(
+                                    "Added new feature line: Start: ({}, {}), End: ({}, {}), {} segments",
+                                    new_line[0].position[0],
+                                    new_line[0].position[1],
+                                    end[0],
+                                    end[1],
+                                    new_line.len() - 1
+                                );
+                                lines.add_line(side, new_line);
+                            }
+                            (event::ElementState::Pressed, event::MouseButton::Right) => {
+                                line_tool.cancel();
+                            }
+                            _ => return,
+                        }
+                    }
+                }
+                event::WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            state: key_state,
+                            virtual_keycode:
+                                Some(event::VirtualKeyCode::LControl)
+                                | Some(event::VirtualKeyCode::RControl),
+                            ..
+                        },
+                    ..
+                } => {
+                    ctrl = key_state == event::ElementState::Pressed;
+                    return;
+                }
+                // Space switches image, P toggles the preview, R renders the morph and Escape cancels it
+                // S asks for suggested line pairs, Y/Enter accepts the shown one, N/Backspace rejects it
+                // E toggles snapping line endpoints to nearby edges and corners
+                // C switches between drawing straight lines, polylines and curves
+                // Tab toggles edit mode, Delete removes the selected line, Ctrl+Z/Ctrl+Y undo and redo
+                // T makes the selected line move with t, in the first half or in the second half
+                // V toggles the live preview, Up/Down pick a morph parameter and Left/Right change it
+                // I switches between moving line ends straight and turning lines about their middles
+                // K steps through the colour spaces the images are blended in
+                // L toggles the loupe and Home resets the zoom of the image being edited
+                // O shows the other image over this one, [ and ] change its opacity, A toggles
+                // lining it up with the lines drawn so far and G shows the other image's lines
+                event::WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            state: event::ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => match key {
+                    event::VirtualKeyCode::Space => {
+                        line_tool.cancel();
+                        edit_tool.clear();
+                        state = state.next(EditorEvent::SwitchImage);
+                    }
+                    event::VirtualKeyCode::P => {
+                        if result.is_some() || state.editing().is_none() {
+                            state = state.next(EditorEvent::TogglePreview);
+                        } else {
+                            println!("Nothing rendered yet, press R to render the morph");
+                        }
+                    }
+                    event::VirtualKeyCode::Escape => {
+                        if let Some(job) = render.take() {
+                            job.cancel();
+                            println!("Rendering cancelled");
+                            state = state.next(EditorEvent::CancelRender);
+                        }
+                    }
+                    event::VirtualKeyCode::R if render.is_some() => return,
+                    event::VirtualKeyCode::R => match lines.complete_lines() {
+                        Some((src_lines, dst_lines)) => {
+                            // The destination is resized to the source so both warps share one grid
+                            let (src_img, dst_img, src_lines, dst_lines) = morph_inputs(
+                                &images.src.pixels,
+                                &images.dst.pixels,
+                                &src_lines,
+                                &dst_lines,
+                                images.src.pixels.dimensions(),
+                            );
+                            render = Some(RenderJob::start(
+                                src_img,
+                                dst_img,
+                                src_lines,
+                                dst_lines,
+                                lines.matched_timings(),
+                                params.tuple(),
+                                params.line_mode,
+                                params.blend_space,
+                            ));
+                            state = state.next(EditorEvent::StartRender);
+                        }
+                        None => {
+                            eprintln!("Must be same number of feature lines on each image!");
+                            if let EditorState::Editing(side) = state {
+                                if lines.next_index(side) < lines.len() {
+                                    state = state.next(EditorEvent::SwitchImage);
+                                }
+                            }
                         }
-                        src_lines.push(VertexBuffer::immutable(&display_src, &new_line).unwrap());
-                        src_lines_ref.push(new_line.clone());
+                    },
+                    _ if state.editing().is_none() => return,
+                    _ if handle_edit_key(key, ctrl, &mut lines, &mut edit_tool) => (),
+                    event::VirtualKeyCode::L => loupe = !loupe,
+                    event::VirtualKeyCode::O => onion.visible = !onion.visible,
+                    event::VirtualKeyCode::G => onion.ghosts = !onion.ghosts,
+                    event::VirtualKeyCode::A => {
+                        onion.aligned = !onion.aligned;
                         println!(
-                            "Added new feature line: Start: ({}, {}), End: ({}, {})",
-                            new_line[0].position[0],
-                            new_line[0].position[1],
-                            new_line[1].position[0],
-                            new_line[1].position[1]
+                            "Onion skin {}",
+                            if onion.aligned {
+                                "lined up with the feature lines"
+                            } else {
+                                "stretched to fit"
+                            }
                         );
-                        line_seg_pt = 2;
-                    };
-                }
+                    }
+                    event::VirtualKeyCode::LBracket => {
+                        onion.opacity = (onion.opacity - 0.1).max(0.1)
+                    }
+                    event::VirtualKeyCode::RBracket => {
+                        onion.opacity = (onion.opacity + 0.1).min(1.0)
+                    }
+                    event::VirtualKeyCode::Home => {
+                        if let Some(side) = state.editing() {
+                            layout.set_pane(side, layout.fitted(side));
+                        }
+                    }
+                    event::VirtualKeyCode::V => {
+                        show_preview = !show_preview;
+                        layout = Layout::new(&images, size, show_preview);
+                    }
+                    event::VirtualKeyCode::Up => params.select(params.values.len() - 1),
+                    event::VirtualKeyCode::Down => params.select(1),
+                    event::VirtualKeyCode::Left => params.adjust(-1.0),
+                    event::VirtualKeyCode::Right => params.adjust(1.0),
+                    event::VirtualKeyCode::E => {
+                        snap_mode = !snap_mode;
+                        println!("Edge snapping {}", if snap_mode { "on" } else { "off" });
+                    }
+                    event::VirtualKeyCode::I => {
+                        params.line_mode = match params.line_mode {
+                            LineInterpolation::Endpoints => LineInterpolation::CentreAngleLength,
+                            LineInterpolation::CentreAngleLength => LineInterpolation::Endpoints,
+                        };
+                    }
+                    event::VirtualKeyCode::K => params.next_blend_space(),
+                    event::VirtualKeyCode::C => {
+                        line_tool.cancel();
+                        line_tool.kind = line_tool.kind.next();
+                        println!("Drawing {}", line_tool.kind.name());
+                    }
+                    event::VirtualKeyCode::S => {
+                        println!("Searching for matching features...");
+                        suggestions = correspondence::suggest_lines(
+                            &images.src.pixels,
+                            &images.dst.pixels,
+                            MAX_SUGGESTIONS,
+                        );
+                        suggestions.reverse();
+                        println!(
+                            "{} suggested feature lines: Y to accept, N to reject",
+                            suggestions.len()
+                        );
+                    }
+                    event::VirtualKeyCode::Y | event::VirtualKeyCode::Return => {
+                        if let Some(suggestion) = suggestions.pop() {
+                            lines.add_pair(suggestion.src_line, suggestion.dst_line);
+                            println!("Accepted suggestion, {} left", suggestions.len());
+                        }
+                    }
+                    event::VirtualKeyCode::N | event::VirtualKeyCode::Back => {
+                        if suggestions.pop().is_some() {
+                            println!("Rejected suggestion, {} left", suggestions.len());
+                        }
+                    }
+                    _ => return,
+                },
                 _ => return,
             },
             event::Event::NewEvents(cause) => match cause {
@@ -253,221 +1550,249 @@ fn main() {
             _ => return,
         }
 
-        if is_src == 1 {
-            let mut target = display_src.draw();
-            target.clear_color(0.0, 0.0, 0.0, 1.0);
+        // A minimised window has nothing to lay out
+        let logical: dpi::LogicalSize<f64> = window_size.to_logical(scale_factor);
+        if window_size.width > 0
+            && window_size.height > 0
+            && (logical.width, logical.height) != (size.x, size.y)
+        {
+            size = Vector2 {
+                x: logical.width,
+                y: logical.height,
+            };
+            layout = layout.resized(&images, size);
+            painter.projection = projection(size);
+            image_painter.projection = projection(size);
+        }
 
-            {
-                let left = position.x - size.x / 2.0;
-                let right = position.x + size.x / 2.0;
-                let bottom = position.y + size.y / 2.0;
-                let top = position.y - size.y / 2.0;
-                let vertex_buf = vec![
-                    Vertex {
-                        position: [left, top],
-                    },
-                    Vertex {
-                        position: [right, top],
-                    },
-                    Vertex {
-                        position: [left, bottom],
-                    },
-                    Vertex {
-                        position: [right, bottom],
-                    },
-                ];
+        if state != prev_state || params != prev_params {
+            set_title(&display, state, &params);
+        }
+        if let Some(side) = state.editing() {
+            let pane = layout.pane(side);
+            cursor = images.get(side).cursor(pane, [x_pos, y_pos], snap_mode);
+            line_tool.follow(cursor);
+        }
 
-                vertices.write(&vertex_buf);
-            }
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
 
-            {
-                let uniforms = uniform! {
-                  projection: perspective,
-                  tex: &src,
-                };
-
-                target
-                    .draw(
-                        &vertices,
-                        &indices,
-                        &program,
-                        &uniforms,
-                        &Default::default(),
-                    )
-                    .unwrap();
-
-                for line in &src_lines[..] {
-                    target
-                        .draw(line, &line_idx, &program, &uniform! {}, &line_params)
-                        .unwrap();
-                }
+        match (state, &result) {
+            (EditorState::Preview(_), Some(result)) | (EditorState::Rendering(_), Some(result)) => {
+                let pane = Pane::fit(result.dimensions(), [0.0, 0.0], [size.x, size.y]);
+                image_painter.draw(&mut target, pane, result, None);
             }
-            target.finish().unwrap();
-        } else {
-            let events_loop_dst = event_loop::EventLoop::new();
-            let wb = window::WindowBuilder::new()
-                .with_inner_size(dpi::LogicalSize::new(1024.0, 768.0))
-                .with_title("Image Morphing Tool");
-            let cb = ContextBuilder::new();
-            let display_dst = glium::Display::new(wb, cb, &events_loop_dst).unwrap();
-            let dst = {
-                let dst_img = image::open(&Path::new(&dst_path)).unwrap().to_rgba();
-                let dst_dim = dst_img.dimensions();
-                let dst_img =
-                    texture::RawImage2d::from_raw_rgba_reversed(&dst_img.into_raw(), dst_dim);
-                texture::SrgbTexture2d::new(&display_dst, dst_img).unwrap()
-            };
-            let mut new_line: Vec<Vertex> = Vec::new();
-            let mut dst_lines: Vec<VertexBuffer<Vertex>> = Vec::new();
-            let mut dst_lines_ref: Vec<Vec<Vertex>> = Vec::new();
-            let mut dst_img = image::open(&Path::new(&dst_path)).unwrap().to_rgba();
-            let src_lines_ref = src_lines_ref.clone();
-            let src_img = src_img.clone();
-
-            events_loop_dst.run(move |event, _, control_flow| {
-                let next_frame_time = Instant::now() + Duration::from_nanos(16_666_667);
-                *control_flow = event_loop::ControlFlow::WaitUntil(next_frame_time);
-
-                if line_seg_pt == 2 {
-                    new_line.clear();
-                    line_seg_pt = 0;
+            _ => {
+                if let Some(pane) = layout.preview {
+                    let (src_lines, dst_lines, timings) =
+                        LivePreview::lines(&edit_tool, &lines, state.editing(), cursor);
+                    live_preview.draw(
+                        &mut target,
+                        &display,
+                        pane,
+                        size,
+                        &src_lines,
+                        &dst_lines,
+                        &timings,
+                        params,
+                    );
+
+                    let (start, end) = layout.slider;
+                    let track = [Vertex { position: start }, Vertex { position: end }];
+                    painter.draw(&mut target, &display, &track, WHITE, 2.0);
+                    let knob = marker(layout.slider_position(params.values[0]));
+                    painter.draw(&mut target, &display, &knob, YELLOW, 3.0);
                 }
 
-                match event {
-                    event::Event::WindowEvent { event, .. } => match event {
-                        event::WindowEvent::CloseRequested => {
-                            if src_lines_ref.len() != dst_lines_ref.len() {
-                                ft_line_err();
-                            }
-                            let morph = Morph::new(
-                                &src_img,
-                                &dst_img,
-                                &src_lines_ref,
-                                &dst_lines_ref,
-                                (0.5, 1.0, 1.0, 1.0)
+                // Zoomed images are kept inside their own column
+                for &side in &[Side::Src, Side::Dst] {
+                    let pane = layout.pane(side);
+                    let clip = framebuffer_rect(&target, size, layout.column_rect(side));
+                    painter.clip = Some(clip);
+                    image_painter.draw(&mut target, pane, &images.get(side).texture, Some(clip));
+
+                    if state.editing() == Some(side) && (onion.visible || onion.ghosts) {
+                        let other = side.other();
+                        let transform = onion.transform(&images, &lines, side);
+                        let to_window = |point| pane.to_window(transform.apply(point));
+                        if onion.visible {
+                            let (w, h) = images.get(other).pixels.dimensions();
+                            let (w, h) = (w as f64, h as f64);
+                            let corners = [
+                                to_window([0.0, 0.0]),
+                                to_window([w, 0.0]),
+                                to_window([0.0, h]),
+                                to_window([w, h]),
+                            ];
+                            image_painter.draw_quad(
+                                &mut target,
+                                corners,
+                                &images.get(other).texture,
+                                Some(clip),
+                                onion.opacity as f32,
                             );
-                            let morphed: RgbaImage = morph.morph();
-                            let image = image::DynamicImage::ImageRgba8(morphed).flipv();
-                            image.save("morphed.png").unwrap();
-                            *control_flow = event_loop::ControlFlow::Exit;
-                            return;
-                        }
-                        event::WindowEvent::CursorMoved {
-                            position: physical_position,
-                            ..
-                        } => {
-                            x_pos = physical_position.x;
-                            y_pos = physical_position.y;
                         }
-                        event::WindowEvent::MouseInput { .. } => {
-                            new_line.push(Vertex {
-                                position: [x_pos, y_pos],
-                            });
-                            if line_seg_pt == 0 {
-                                line_seg_pt = 1;
-                            } else {
-                                if dst_lines.is_empty() {
-                                    let image: texture::RawImage2d<u8> =
-                                        display_dst.read_front_buffer().unwrap();
-                                    let image: image::RgbaImage = image::ImageBuffer::from_raw(
-                                        image.width,
-                                        image.height,
-                                        image.data.into_owned(),
-                                    )
-                                    .unwrap();
-                                    dst_img = image;
-                                }
-                                dst_lines.push(
-                                    VertexBuffer::immutable(&display_dst, &new_line).unwrap(),
-                                );
-                                dst_lines_ref.push(new_line.clone());
-                                println!(
-                                    "Added new feature line: Start: ({}, {}), End: ({}, {})",
-                                    new_line[0].position[0],
-                                    new_line[0].position[1],
-                                    new_line[1].position[0],
-                                    new_line[1].position[1]
-                                );
-                                line_seg_pt = 2;
-                            };
+                        if onion.ghosts {
+                            let mut ghosts = strokes::Strokes::new();
+                            for (index, line) in lines.lines(other) {
+                                let [r, g, b, _] = pair_color(index);
+                                let color = [r, g, b, 0.5];
+                                let points: Vec<[f64; 2]> =
+                                    line.iter().map(|v| to_window(v.position)).collect();
+                                let (a, b) = halfway(&points);
+                                ghosts.polyline(&points, 1.5, color);
+                                ghosts.arrow(a, b, 7.0, 1.0, color);
+                            }
+                            painter.draw_strokes(&mut target, &display, &ghosts);
                         }
-                        _ => return,
-                    },
-                    event::Event::NewEvents(cause) => match cause {
-                        event::StartCause::ResumeTimeReached { .. } => (),
-                        event::StartCause::Init => (),
-                        _ => return,
-                    },
-                    _ => return,
-                }
+                    }
 
-                let (vertices, indices, line_params) = {
-                    let data: Vec<u16> = vec![0, 1, 2, 1, 3, 2];
-                    let vertex_buf = VertexBuffer::empty_dynamic(&display_dst, 4).unwrap();
-                    let index_buf =
-                        IndexBuffer::new(&display_dst, index::PrimitiveType::TrianglesList, &data)
-                            .unwrap();
-                    let line_params = DrawParameters {
-                        line_width: Some(2.0),
-                        ..Default::default()
+                    // The line to draw next is the one whose partner is missing here
+                    let editing = state.editing();
+                    let next = editing.map(|active| lines.next_index(active));
+                    let highlight = match editing {
+                        Some(active) if active != side => next.filter(|&i| i < lines.len()),
+                        _ => None,
                     };
-                    (vertex_buf, index_buf, line_params)
-                };
-
-                let program =
-                    glium::Program::from_source(&display_dst, VERTEX_SHADER, FRAGMENT_SHADER, None)
-                        .unwrap();
-
-                let mut target = display_dst.draw();
-                target.clear_color(0.0, 0.0, 0.0, 1.0);
-
-                {
-                    let left = position.x - size.x / 2.0;
-                    let right = position.x + size.x / 2.0;
-                    let bottom = position.y + size.y / 2.0;
-                    let top = position.y - size.y / 2.0;
-                    let vertex_buf = vec![
-                        Vertex {
-                            position: [left, top],
-                        },
-                        Vertex {
-                            position: [right, top],
-                        },
-                        Vertex {
-                            position: [left, bottom],
-                        },
-                        Vertex {
-                            position: [right, bottom],
-                        },
-                    ];
+                    let drag_cursor = editing.filter(|&active| active == side).map(|_| cursor);
+                    draw_feature_lines(
+                        &mut target,
+                        &display,
+                        &painter,
+                        pane,
+                        &lines,
+                        side,
+                        &edit_tool,
+                        drag_cursor,
+                        highlight,
+                    );
 
-                    vertices.write(&vertex_buf);
+                    if let Some(suggestion) = suggestions.last() {
+                        let line = match side {
+                            Side::Src => &suggestion.src_line,
+                            Side::Dst => &suggestion.dst_line,
+                        };
+                        painter.draw_in(&mut target, &display, pane, line, YELLOW, 4.0);
+                    }
                 }
 
-                {
-                    let uniforms = uniform! {
-                      projection: perspective,
-                      tex: &dst,
-                    };
+                if let Some(side) = state.editing() {
+                    let pane = layout.pane(side);
+                    painter.clip = Some(framebuffer_rect(&target, size, layout.column_rect(side)));
+                    let frame = quad(pane.rect());
+                    let outline = [frame[0], frame[1], frame[3], frame[2], frame[0]];
+                    painter.draw(&mut target, &display, &outline, WHITE, 1.0);
+
+                    if let Some(rubber_band) = line_tool.preview(cursor) {
+                        let color = pair_color(lines.next_index(side));
+                        painter.draw_in(&mut target, &display, pane, &rubber_band, color, 2.0);
+                    }
 
-                    target
-                        .draw(
-                            &vertices,
-                            &indices,
-                            &program,
-                            &uniforms,
-                            &Default::default(),
-                        )
-                        .unwrap();
-
-                    for line in &dst_lines[..] {
-                        target
-                            .draw(line, &line_idx, &program, &uniform! {}, &line_params)
-                            .unwrap();
+                    if snap_mode {
+                        let preview = marker(pane.to_window(cursor));
+                        painter.draw(&mut target, &display, &preview, WHITE, 2.0);
+                    }
+
+                    // Magnified view of the image around the cursor, beside it
+                    if loupe && pane.contains([x_pos, y_pos]) {
+                        let offset = if x_pos + 20.0 + LOUPE_SIZE > size.x {
+                            -20.0 - LOUPE_SIZE
+                        } else {
+                            20.0
+                        };
+                        let top_left = [x_pos + offset, (y_pos - LOUPE_SIZE / 2.0).max(0.0)];
+                        let bottom_right = [top_left[0] + LOUPE_SIZE, top_left[1] + LOUPE_SIZE];
+                        let centre = [
+                            top_left[0] + LOUPE_SIZE / 2.0,
+                            top_left[1] + LOUPE_SIZE / 2.0,
+                        ];
+                        let [px, py] = pane.to_window(cursor);
+                        let magnified = pane
+                            .panned([centre[0] - px, centre[1] - py])
+                            .zoom_at(centre, LOUPE_ZOOM);
+                        let clip = framebuffer_rect(&target, size, (top_left, bottom_right));
+                        target.clear(Some(&clip), Some((0.0, 0.0, 0.0, 1.0)), false, None, None);
+                        image_painter.draw(
+                            &mut target,
+                            magnified,
+                            &images.get(side).texture,
+                            Some(clip),
+                        );
+                        painter.clip = Some(clip);
+                        draw_feature_lines(
+                            &mut target,
+                            &display,
+                            &painter,
+                            magnified,
+                            &lines,
+                            side,
+                            &edit_tool,
+                            Some(cursor),
+                            None,
+                        );
+                        if let Some(rubber_band) = line_tool.preview(cursor) {
+                            let color = pair_color(lines.next_index(side));
+                            painter.draw_in(
+                                &mut target,
+                                &display,
+                                magnified,
+                                &rubber_band,
+                                color,
+                                2.0,
+                            );
+                        }
+                        let [cx, cy] = magnified.to_window(cursor);
+                        for cross in &[
+                            [[cx - 6.0, cy], [cx + 6.0, cy]],
+                            [[cx, cy - 6.0], [cx, cy + 6.0]],
+                        ] {
+                            let cross =
+                                [Vertex { position: cross[0] }, Vertex { position: cross[1] }];
+                            painter.draw(&mut target, &display, &cross, WHITE, 1.0);
+                        }
+                        let frame = quad((top_left, bottom_right));
+                        let outline = [frame[0], frame[1], frame[3], frame[2], frame[0]];
+                        painter.clip = None;
+                        painter.draw(&mut target, &display, &outline, WHITE, 2.0);
                     }
                 }
-                target.finish().unwrap();
-            })
+                painter.clip = None;
+            }
+        }
+        if let Some(job) = &render {
+            let (left, right, y) = (size.x * 0.25, size.x * 0.75, size.y - 30.0);
+            let mut bar = strokes::Strokes::new();
+            bar.segment([left, y], [right, y], 14.0, [0.0, 0.0, 0.0, 0.7]);
+            bar.polyline(
+                &[
+                    [left - 2.0, y - 9.0],
+                    [right + 2.0, y - 9.0],
+                    [right + 2.0, y + 9.0],
+                    [left - 2.0, y + 9.0],
+                    [left - 2.0, y - 9.0],
+                ],
+                2.0,
+                WHITE,
+            );
+            bar.segment(
+                [left, y],
+                [left + (right - left) * job.progress, y],
+                12.0,
+                YELLOW,
+            );
+            painter.draw_strokes(&mut target, &display, &bar);
+        }
+        target.finish().unwrap();
+
+        if let Some((morphed, saved)) = render.as_mut().and_then(RenderJob::poll) {
+            match saved {
+                Ok(()) => println!("Saved morphed.png"),
+                Err(err) => eprintln!("Couldn't save morphed.png: {}", err),
+            }
+            render = None;
+            result = Some(upload(&display, morphed));
+            state = state.next(EditorEvent::RenderFinished);
+            set_title(&display, state, &params);
         }
     });
 }
@@ -0,0 +1,171 @@
+// Where colours are sampled and mixed. 8 bit images hold gamma encoded sRGB, and mixing those
+// values directly makes the middle of a dissolve darker and muddier than either image
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum BlendSpace {
+    Srgb, // The encoded values as they are, as the morph used to blend
+    #[default]
+    Linear, // Linear light, decoded from sRGB and encoded again afterwards
+    Oklab, // Sampled in linear light and mixed in OKLab, perceptually even
+    Lab,  // Sampled in linear light and mixed in CIELAB under D65
+}
+
+impl BlendSpace {
+    // The values samples are weighted in: the 0 to 255 channels themselves for Srgb, linear
+    // light from 0 to 1 for the others
+    pub fn decode(self, rgb: [f64; 3]) -> [f64; 3] {
+        match self {
+            BlendSpace::Srgb => rgb,
+            _ => [
+                srgb_to_linear(rgb[0]),
+                srgb_to_linear(rgb[1]),
+                srgb_to_linear(rgb[2]),
+            ],
+        }
+    }
+
+    // Back from decode to 0 to 255 channels. Srgb values are left unrounded as before, so the
+    // usual conversion to u8 still truncates them
+    pub fn encode(self, decoded: [f64; 3]) -> [f64; 3] {
+        match self {
+            BlendSpace::Srgb => decoded,
+            _ => [
+                linear_to_srgb(decoded[0]),
+                linear_to_srgb(decoded[1]),
+                linear_to_srgb(decoded[2]),
+            ],
+        }
+    }
+
+    // Weighted mix of decoded colours, encoded again
+    pub fn mix(self, colors: &[([f64; 3], f64)]) -> [f64; 3] {
+        let mut sum = [0.0; 3];
+        for &(color, weight) in colors {
+            let coords = self.coords(color);
+            for c in 0..3 {
+                sum[c] += coords[c] * weight;
+            }
+        }
+        self.encode(self.decoded(sum))
+    }
+
+    // Decoded colour in the space it's mixed in
+    pub(crate) fn coords(self, decoded: [f64; 3]) -> [f64; 3] {
+        match self {
+            BlendSpace::Srgb | BlendSpace::Linear => decoded,
+            BlendSpace::Oklab => linear_to_oklab(decoded),
+            BlendSpace::Lab => linear_to_lab(decoded),
+        }
+    }
+
+    pub(crate) fn decoded(self, coords: [f64; 3]) -> [f64; 3] {
+        match self {
+            BlendSpace::Srgb | BlendSpace::Linear => coords,
+            BlendSpace::Oklab => oklab_to_linear(coords),
+            BlendSpace::Lab => lab_to_linear(coords),
+        }
+    }
+}
+
+// An 8 bit sRGB channel value as linear light from 0 to 1
+pub fn srgb_to_linear(value: f64) -> f64 {
+    let c = value / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Linear light as the nearest 8 bit sRGB channel value, clamped to 0 to 255
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    let l = linear.clamp(0.0, 1.0);
+    let c = if l <= 0.003_130_8 {
+        12.92 * l
+    } else {
+        1.055 * l.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round()
+}
+
+fn times(m: [[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+// Björn Ottosson's matrices from linear sRGB through cone responses
+const OKLAB_LMS: [[f64; 3]; 3] = [
+    [0.412_221_470_8, 0.536_332_536_3, 0.051_445_992_9],
+    [0.211_903_498_2, 0.680_699_545_1, 0.107_396_956_6],
+    [0.088_302_461_9, 0.281_718_837_6, 0.629_978_700_5],
+];
+const OKLAB_LAB: [[f64; 3]; 3] = [
+    [0.210_454_255_3, 0.793_617_785_0, -0.004_072_046_8],
+    [1.977_998_495_1, -2.428_592_205_0, 0.450_593_709_9],
+    [0.025_904_037_1, 0.782_771_766_2, -0.808_675_766_0],
+];
+const OKLAB_LMS_INVERSE: [[f64; 3]; 3] = [
+    [1.0, 0.396_337_777_4, 0.215_803_757_3],
+    [1.0, -0.105_561_345_8, -0.063_854_172_8],
+    [1.0, -0.089_484_177_5, -1.291_485_548_0],
+];
+const OKLAB_RGB: [[f64; 3]; 3] = [
+    [4.076_741_662_1, -3.307_711_591_3, 0.230_969_929_2],
+    [-1.268_438_004_6, 2.609_757_401_1, -0.341_319_396_5],
+    [-0.004_196_086_3, -0.703_418_614_7, 1.707_614_701_0],
+];
+
+pub fn linear_to_oklab(rgb: [f64; 3]) -> [f64; 3] {
+    let lms = times(OKLAB_LMS, rgb);
+    times(OKLAB_LAB, [lms[0].cbrt(), lms[1].cbrt(), lms[2].cbrt()])
+}
+
+pub fn oklab_to_linear(lab: [f64; 3]) -> [f64; 3] {
+    let lms = times(OKLAB_LMS_INVERSE, lab);
+    times(OKLAB_RGB, [lms[0].powi(3), lms[1].powi(3), lms[2].powi(3)])
+}
+
+// Linear sRGB to CIE XYZ and back, with the D65 white point
+const XYZ: [[f64; 3]; 3] = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175_0],
+    [0.019_333_9, 0.119_192_0, 0.950_304_1],
+];
+const XYZ_RGB: [[f64; 3]; 3] = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266_0, 1.876_010_8, 0.041_556_0],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+const WHITE: [f64; 3] = [0.950_47, 1.0, 1.088_83];
+const LAB_DELTA: f64 = 6.0 / 29.0;
+
+pub fn linear_to_lab(rgb: [f64; 3]) -> [f64; 3] {
+    let xyz = times(XYZ, rgb);
+    let f = |i: usize| {
+        let t = xyz[i] / WHITE[i];
+        if t > LAB_DELTA.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(0), f(1), f(2));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+pub fn lab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let f = [fy + a / 500.0, fy, fy - b / 200.0];
+    let mut xyz = [0.0; 3];
+    for i in 0..3 {
+        let t = if f[i] > LAB_DELTA {
+            f[i].powi(3)
+        } else {
+            3.0 * LAB_DELTA * LAB_DELTA * (f[i] - 4.0 / 29.0)
+        };
+        xyz[i] = t * WHITE[i];
+    }
+    times(XYZ_RGB, xyz)
+}
@@ -0,0 +1,211 @@
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use crate::BlendSpace;
+
+// How the colours of the two warped images are brought together before the dissolve, so
+// photos under different lighting don't cross like a double exposure
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorTransfer {
+    Reinhard,  // Each channel's mean and spread, measured in the morph's blend space
+    Histogram, // Each channel's whole distribution of 8 bit values
+}
+
+// Both warps with their colour statistics moved to the mix of the two at t, so at t = 0 the
+// source keeps its colours and the destination takes them on. With masks, only pixels inside
+// them are measured and changed, weighted by the masks' red channel. Transparent pixels,
+// where a warp falls off its image, are left out
+pub fn transfer(
+    method: ColorTransfer,
+    src_warp: &RgbaImage,
+    dst_warp: &RgbaImage,
+    masks: Option<(&RgbaImage, &RgbaImage)>,
+    t: f64,
+    space: BlendSpace,
+) -> (RgbaImage, RgbaImage) {
+    let src_weights = weights(src_warp, masks.map(|(src_mask, _)| src_mask));
+    let dst_weights = weights(dst_warp, masks.map(|(_, dst_mask)| dst_mask));
+    if src_weights.iter().sum::<f64>() <= 0.0 || dst_weights.iter().sum::<f64>() <= 0.0 {
+        return (src_warp.clone(), dst_warp.clone());
+    }
+    match method {
+        ColorTransfer::Reinhard => {
+            let src_stats = Stats::new(src_warp, &src_weights, space);
+            let dst_stats = Stats::new(dst_warp, &dst_weights, space);
+            let target = src_stats.mix(&dst_stats, t);
+            (
+                apply(src_warp, &src_weights, |rgb| {
+                    src_stats.transfer(&target, rgb, space)
+                }),
+                apply(dst_warp, &dst_weights, |rgb| {
+                    dst_stats.transfer(&target, rgb, space)
+                }),
+            )
+        }
+        ColorTransfer::Histogram => {
+            let src_hist = Histogram::new(src_warp, &src_weights);
+            let dst_hist = Histogram::new(dst_warp, &dst_weights);
+            let src_table = src_hist.table(&src_hist, &dst_hist, t);
+            let dst_table = dst_hist.table(&src_hist, &dst_hist, t);
+            (
+                apply(src_warp, &src_weights, |rgb| lookup(&src_table, rgb)),
+                apply(dst_warp, &dst_weights, |rgb| lookup(&dst_table, rgb)),
+            )
+        }
+    }
+}
+
+// How much each pixel counts, from 0 to 1
+fn weights(img: &RgbaImage, mask: Option<&RgbaImage>) -> Vec<f64> {
+    if let Some(mask) = mask {
+        assert_eq!(
+            mask.dimensions(),
+            img.dimensions(),
+            "mask must match its image"
+        );
+    }
+    img.enumerate_pixels()
+        .map(|(x, y, Rgba(pixel))| {
+            let coverage = mask.map_or(1.0, |mask| mask.get_pixel(x, y)[0] as f64 / 255.0);
+            pixel[3] as f64 / 255.0 * coverage
+        })
+        .collect()
+}
+
+// Every weighted pixel's colour replaced by transfer's and mixed back by its weight
+fn apply<F>(img: &RgbaImage, weights: &[f64], transfer: F) -> RgbaImage
+where
+    F: Fn([f64; 3]) -> [f64; 3],
+{
+    let width = img.width();
+    ImageBuffer::from_fn(width, img.height(), |x, y| {
+        let Rgba(pixel) = *img.get_pixel(x, y);
+        let weight = weights[(y * width + x) as usize];
+        if weight <= 0.0 {
+            return Rgba(pixel);
+        }
+        let rgb = [pixel[0] as f64, pixel[1] as f64, pixel[2] as f64];
+        let moved = transfer(rgb);
+        let mut out = pixel;
+        for c in 0..3 {
+            out[c] = (rgb[c] + weight * (moved[c] - rgb[c]))
+                .round()
+                .clamp(0.0, 255.0) as u8;
+        }
+        Rgba(out)
+    })
+}
+
+// Weighted mean and standard deviation of each channel in a blend space's coordinates
+struct Stats {
+    mean: [f64; 3],
+    deviation: [f64; 3],
+}
+
+impl Stats {
+    fn new(img: &RgbaImage, weights: &[f64], space: BlendSpace) -> Self {
+        let mut total = 0.0;
+        let mut sum = [0.0; 3];
+        let mut squares = [0.0; 3];
+        for (Rgba(pixel), &weight) in img.pixels().zip(weights) {
+            let coords =
+                space.coords(space.decode([pixel[0] as f64, pixel[1] as f64, pixel[2] as f64]));
+            total += weight;
+            for c in 0..3 {
+                sum[c] += weight * coords[c];
+                squares[c] += weight * coords[c] * coords[c];
+            }
+        }
+        let mut mean = [0.0; 3];
+        let mut deviation = [0.0; 3];
+        for c in 0..3 {
+            mean[c] = sum[c] / total;
+            deviation[c] = (squares[c] / total - mean[c] * mean[c]).max(0.0).sqrt();
+        }
+        Stats { mean, deviation }
+    }
+
+    fn mix(&self, other: &Stats, t: f64) -> Stats {
+        let mut mean = [0.0; 3];
+        let mut deviation = [0.0; 3];
+        for c in 0..3 {
+            mean[c] = (1.0 - t) * self.mean[c] + t * other.mean[c];
+            deviation[c] = (1.0 - t) * self.deviation[c] + t * other.deviation[c];
+        }
+        Stats { mean, deviation }
+    }
+
+    // Reinhard et al.'s transfer: centred on this mean, scaled to the target's spread and moved
+    // to the target's mean. Flat channels are only moved
+    fn transfer(&self, target: &Stats, rgb: [f64; 3], space: BlendSpace) -> [f64; 3] {
+        let mut coords = space.coords(space.decode(rgb));
+        for (c, coord) in coords.iter_mut().enumerate() {
+            let scale = if self.deviation[c] > 1e-6 {
+                target.deviation[c] / self.deviation[c]
+            } else {
+                1.0
+            };
+            *coord = (*coord - self.mean[c]) * scale + target.mean[c];
+        }
+        space.encode(space.decoded(coords))
+    }
+}
+
+// Weighted counts of each channel's 8 bit values
+struct Histogram {
+    counts: [[f64; 256]; 3],
+    total: f64,
+}
+
+impl Histogram {
+    fn new(img: &RgbaImage, weights: &[f64]) -> Self {
+        let mut counts = [[0.0; 256]; 3];
+        let mut total = 0.0;
+        for (Rgba(pixel), &weight) in img.pixels().zip(weights) {
+            total += weight;
+            for c in 0..3 {
+                counts[c][pixel[c] as usize] += weight;
+            }
+        }
+        Histogram { counts, total }
+    }
+
+    // The fraction of channel c below value, counting half of value's own pixels
+    fn rank(&self, c: usize, value: usize) -> f64 {
+        let below: f64 = self.counts[c][..value].iter().sum();
+        (below + self.counts[c][value] / 2.0) / self.total
+    }
+
+    // The value at rank, each value's pixels spread evenly from half below it to half above
+    fn quantile(&self, c: usize, rank: f64) -> f64 {
+        let target = rank * self.total;
+        let mut below = 0.0;
+        for (value, &count) in self.counts[c].iter().enumerate() {
+            if count > 0.0 && below + count >= target {
+                let value = value as f64 - 0.5 + (target - below) / count;
+                return value.clamp(0.0, 255.0);
+            }
+            below += count;
+        }
+        255.0
+    }
+
+    // For every value in this image, the value at the same rank in the mix of src and dst
+    fn table(&self, src: &Histogram, dst: &Histogram, t: f64) -> [[f64; 256]; 3] {
+        let mut table = [[0.0; 256]; 3];
+        for (c, values) in table.iter_mut().enumerate() {
+            for (value, moved) in values.iter_mut().enumerate() {
+                let rank = self.rank(c, value);
+                *moved = (1.0 - t) * src.quantile(c, rank) + t * dst.quantile(c, rank);
+            }
+        }
+        table
+    }
+}
+
+fn lookup(table: &[[f64; 256]; 3], rgb: [f64; 3]) -> [f64; 3] {
+    [
+        table[0][rgb[0] as usize],
+        table[1][rgb[1] as usize],
+        table[2][rgb[2] as usize],
+    ]
+}
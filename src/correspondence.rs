@@ -0,0 +1,266 @@
+use image::{GrayImage, RgbaImage};
+use imageproc::corners::corners_fast9;
+use imageproc::filter::gaussian_blur_f32;
+use imageproc::suppress::local_maxima;
+
+use crate::Vertex;
+
+const PATCH_RADIUS: i32 = 15; // Half size of the square patch sampled by each descriptor
+const DESCRIPTOR_WORDS: usize = 4; // 256 bit binary descriptor
+const FAST_THRESHOLD: u8 = 20;
+const SUPPRESS_RADIUS: u32 = 4;
+const MAX_KEYPOINTS: usize = 500;
+const MAX_HAMMING: u32 = 64; // Matches further apart than this are never accepted
+const RATIO: f64 = 0.8; // Best match must be clearly better than the second best
+const RANSAC_ITERATIONS: usize = 500;
+const RANSAC_THRESHOLD: f64 = 0.02; // Inlier distance as a fraction of the image diagonal
+const MIN_LINE_LEN: f64 = 0.05; // Shortest suggested line as a fraction of the image diagonal
+
+#[derive(Clone, Debug)]
+pub struct Keypoint {
+    pub x: f64,
+    pub y: f64,
+    pub score: f32,
+    descriptor: [u64; DESCRIPTOR_WORDS],
+}
+
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub src_line: Vec<Vertex>,
+    pub dst_line: Vec<Vertex>,
+    pub score: f64,
+}
+
+// Small deterministic generator so suggestions are the same on every run
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// Fixed BRIEF sampling pattern: pairs of offsets inside the patch
+fn sample_pairs() -> Vec<((i32, i32), (i32, i32))> {
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let span = (2 * PATCH_RADIUS + 1) as usize;
+    let mut offset = || rng.below(span) as i32 - PATCH_RADIUS;
+    (0..DESCRIPTOR_WORDS * 64)
+        .map(|_| ((offset(), offset()), (offset(), offset())))
+        .collect()
+}
+
+fn hamming(a: &[u64; DESCRIPTOR_WORDS], b: &[u64; DESCRIPTOR_WORDS]) -> u32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum()
+}
+
+pub fn detect_keypoints(img: &RgbaImage, max_points: usize) -> Vec<Keypoint> {
+    let gray: GrayImage = image::imageops::grayscale(img);
+    let (width, height) = gray.dimensions();
+    let blurred = gaussian_blur_f32(&gray, 2.0);
+    let pairs = sample_pairs();
+
+    let mut corners = local_maxima(&corners_fast9(&gray, FAST_THRESHOLD), SUPPRESS_RADIUS);
+    corners.retain(|c| {
+        c.x as i32 >= PATCH_RADIUS
+            && c.y as i32 >= PATCH_RADIUS
+            && (c.x as i32) < width as i32 - PATCH_RADIUS
+            && (c.y as i32) < height as i32 - PATCH_RADIUS
+    });
+    corners.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    corners.truncate(max_points);
+
+    corners
+        .iter()
+        .map(|c| {
+            let mut descriptor = [0u64; DESCRIPTOR_WORDS];
+            for (bit, ((x0, y0), (x1, y1))) in pairs.iter().enumerate() {
+                let p0 = blurred.get_pixel((c.x as i32 + x0) as u32, (c.y as i32 + y0) as u32);
+                let p1 = blurred.get_pixel((c.x as i32 + x1) as u32, (c.y as i32 + y1) as u32);
+                if p0.0[0] < p1.0[0] {
+                    descriptor[bit / 64] |= 1 << (bit % 64);
+                }
+            }
+            Keypoint {
+                x: c.x as f64,
+                y: c.y as f64,
+                score: c.score,
+                descriptor,
+            }
+        })
+        .collect()
+}
+
+fn best_two(kp: &Keypoint, candidates: &[Keypoint]) -> Option<(usize, u32, u32)> {
+    let mut best: Option<(usize, u32)> = None;
+    let mut second = u32::MAX;
+    for (j, other) in candidates.iter().enumerate() {
+        let dist = hamming(&kp.descriptor, &other.descriptor);
+        match best {
+            Some((_, best_dist)) if dist >= best_dist => second = second.min(dist),
+            Some((_, best_dist)) => {
+                second = best_dist;
+                best = Some((j, dist));
+            }
+            None => best = Some((j, dist)),
+        }
+    }
+    best.map(|(j, dist)| (j, dist, second))
+}
+
+// Mutual nearest neighbours passing the ratio test, as (src index, dst index, distance)
+pub fn match_keypoints(src: &[Keypoint], dst: &[Keypoint]) -> Vec<(usize, usize, u32)> {
+    let mut matches = Vec::new();
+    for (i, kp) in src.iter().enumerate() {
+        let (j, dist, second) = match best_two(kp, dst) {
+            Some(found) => found,
+            None => continue,
+        };
+        if dist > MAX_HAMMING || dist as f64 > RATIO * second as f64 {
+            continue;
+        }
+        if let Some((back, _, _)) = best_two(&dst[j], src) {
+            if back == i {
+                matches.push((i, j, dist));
+            }
+        }
+    }
+    matches
+}
+
+// Similarity transform z' = s * z + t in complex form, returned as (s, t)
+fn similarity(a: (f64, f64), b: (f64, f64), a2: (f64, f64), b2: (f64, f64)) -> Option<[f64; 4]> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (dx2, dy2) = (b2.0 - a2.0, b2.1 - a2.1);
+    let len = dx * dx + dy * dy;
+    if len < 1e-6 {
+        return None;
+    }
+    let s_re = (dx2 * dx + dy2 * dy) / len;
+    let s_im = (dy2 * dx - dx2 * dy) / len;
+    let t_re = a2.0 - (s_re * a.0 - s_im * a.1);
+    let t_im = a2.1 - (s_re * a.1 + s_im * a.0);
+    Some([s_re, s_im, t_re, t_im])
+}
+
+// Keeps the matches consistent with the best similarity transform found by RANSAC
+pub fn ransac_filter(
+    src: &[Keypoint],
+    dst: &[Keypoint],
+    matches: &[(usize, usize, u32)],
+    threshold: f64,
+) -> Vec<(usize, usize, u32)> {
+    if matches.len() < 2 {
+        return Vec::new();
+    }
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let point = |kp: &Keypoint| (kp.x, kp.y);
+    let inliers_of = |model: &[f64; 4]| -> Vec<(usize, usize, u32)> {
+        matches
+            .iter()
+            .cloned()
+            .filter(|&(i, j, _)| {
+                let (x, y) = point(&src[i]);
+                let px = model[0] * x - model[1] * y + model[2];
+                let py = model[0] * y + model[1] * x + model[3];
+                ((px - dst[j].x).powi(2) + (py - dst[j].y).powi(2)).sqrt() < threshold
+            })
+            .collect()
+    };
+
+    let mut best: Vec<(usize, usize, u32)> = Vec::new();
+    for _ in 0..RANSAC_ITERATIONS {
+        let m0 = matches[rng.below(matches.len())];
+        let m1 = matches[rng.below(matches.len())];
+        if m0.0 == m1.0 {
+            continue;
+        }
+        let model = match similarity(
+            point(&src[m0.0]),
+            point(&src[m1.0]),
+            point(&dst[m0.1]),
+            point(&dst[m1.1]),
+        ) {
+            Some(model) => model,
+            None => continue,
+        };
+        let inliers = inliers_of(&model);
+        if inliers.len() > best.len() {
+            best = inliers;
+        }
+    }
+    best
+}
+
+pub fn suggest_lines(src: &RgbaImage, dst: &RgbaImage, max_lines: usize) -> Vec<Suggestion> {
+    let src_kps = detect_keypoints(src, MAX_KEYPOINTS);
+    let dst_kps = detect_keypoints(dst, MAX_KEYPOINTS);
+    let (src_w, src_h) = src.dimensions();
+    let (dst_w, dst_h) = dst.dimensions();
+    let src_diag = ((src_w * src_w + src_h * src_h) as f64).sqrt();
+    let dst_diag = ((dst_w * dst_w + dst_h * dst_h) as f64).sqrt();
+
+    let matches = match_keypoints(&src_kps, &dst_kps);
+    let mut inliers = ransac_filter(&src_kps, &dst_kps, &matches, RANSAC_THRESHOLD * dst_diag);
+    inliers.sort_by_key(|&(_, _, dist)| dist);
+
+    // Pair each inlier with its closest unused partner that is long enough in both images
+    let mut used = vec![false; inliers.len()];
+    let mut suggestions = Vec::new();
+    for a in 0..inliers.len() {
+        if used[a] || suggestions.len() >= max_lines {
+            continue;
+        }
+        let (si, di, dist_a) = inliers[a];
+        let mut partner: Option<(usize, f64)> = None;
+        for b in 0..inliers.len() {
+            if b == a || used[b] {
+                continue;
+            }
+            let (sj, dj, _) = inliers[b];
+            let src_len = (src_kps[si].x - src_kps[sj].x).hypot(src_kps[si].y - src_kps[sj].y);
+            let dst_len = (dst_kps[di].x - dst_kps[dj].x).hypot(dst_kps[di].y - dst_kps[dj].y);
+            if src_len < MIN_LINE_LEN * src_diag || dst_len < MIN_LINE_LEN * dst_diag {
+                continue;
+            }
+            if partner.map_or(true, |(_, len)| src_len < len) {
+                partner = Some((b, src_len));
+            }
+        }
+        if let Some((b, _)) = partner {
+            let (sj, dj, dist_b) = inliers[b];
+            used[a] = true;
+            used[b] = true;
+            suggestions.push(Suggestion {
+                src_line: vec![
+                    Vertex {
+                        position: [src_kps[si].x, src_kps[si].y],
+                    },
+                    Vertex {
+                        position: [src_kps[sj].x, src_kps[sj].y],
+                    },
+                ],
+                dst_line: vec![
+                    Vertex {
+                        position: [dst_kps[di].x, dst_kps[di].y],
+                    },
+                    Vertex {
+                        position: [dst_kps[dj].x, dst_kps[dj].y],
+                    },
+                ],
+                score: 1.0 - (dist_a + dist_b) as f64 / (2 * DESCRIPTOR_WORDS * 64) as f64,
+            });
+        }
+    }
+    suggestions
+}
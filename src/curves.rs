@@ -0,0 +1,186 @@
+use crate::{LineSet, LineTiming, Vertex};
+
+// Points along a piecewise cubic Bezier, steps per span. The controls are the start point
+// followed by three per span, the last of which is where the next span starts
+pub fn bezier(controls: &[[f64; 2]], steps: usize) -> Vec<[f64; 2]> {
+    let mut points: Vec<[f64; 2]> = controls.iter().take(1).cloned().collect();
+    for span in controls.windows(4).step_by(3) {
+        for i in 1..=steps {
+            let t = i as f64 / steps as f64;
+            let s = 1.0 - t;
+            let weights = [s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t];
+            let mut point = [0.0, 0.0];
+            for (control, weight) in span.iter().zip(weights.iter()) {
+                point[0] += control[0] * weight;
+                point[1] += control[1] * weight;
+            }
+            points.push(point);
+        }
+    }
+    points
+}
+
+// Bezier controls of the Catmull-Rom spline through the points, so the curve passes through every one
+pub fn smooth(points: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    if points.len() < 2 {
+        return points.to_vec();
+    }
+    let last = points.len() - 1;
+    let tangent = |i: usize| {
+        let (before, after) = (points[i.saturating_sub(1)], points[(i + 1).min(last)]);
+        [(after[0] - before[0]) / 2.0, (after[1] - before[1]) / 2.0]
+    };
+    let mut controls = vec![points[0]];
+    for i in 0..last {
+        let (start, end) = (points[i], points[i + 1]);
+        let (m0, m1) = (tangent(i), tangent(i + 1));
+        controls.push([start[0] + m0[0] / 3.0, start[1] + m0[1] / 3.0]);
+        controls.push([end[0] - m1[0] / 3.0, end[1] - m1[1] / 3.0]);
+        controls.push(end);
+    }
+    controls
+}
+
+// Ramer-Douglas-Peucker, keeps the ends and every point further than tolerance from the simplified path
+pub fn simplify(points: &[[f64; 2]], tolerance: f64) -> Vec<[f64; 2]> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (index, dist) = points[1..points.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, &point)| (i + 1, segment_distance(first, last, point)))
+        .fold(
+            (0, 0.0),
+            |best, next| if next.1 > best.1 { next } else { best },
+        );
+    if dist <= tolerance {
+        return vec![first, last];
+    }
+    let mut kept = simplify(&points[..=index], tolerance);
+    kept.pop(); // Starts the second half as well
+    kept.extend(simplify(&points[index..], tolerance));
+    kept
+}
+
+// How far along the segment from p to q its point nearest x is, from 0 at p to 1 at q
+pub fn segment_fraction(p: [f64; 2], q: [f64; 2], x: [f64; 2]) -> f64 {
+    let (dx, dy) = (q[0] - p[0], q[1] - p[1]);
+    let len = dx * dx + dy * dy;
+    if len < f64::EPSILON {
+        0.0
+    } else {
+        (((x[0] - p[0]) * dx + (x[1] - p[1]) * dy) / len).clamp(0.0, 1.0)
+    }
+}
+
+pub fn segment_distance(p: [f64; 2], q: [f64; 2], x: [f64; 2]) -> f64 {
+    let u = segment_fraction(p, q, x);
+    (p[0] + u * (q[0] - p[0]) - x[0]).hypot(p[1] + u * (q[1] - p[1]) - x[1])
+}
+
+// How far along the polyline each point is, as a fraction of its length
+fn fractions(points: &[Vertex]) -> Vec<f64> {
+    let mut lengths = vec![0.0];
+    for pair in points.windows(2) {
+        let [x0, y0] = pair[0].position;
+        let [x1, y1] = pair[1].position;
+        lengths.push(lengths[lengths.len() - 1] + (x1 - x0).hypot(y1 - y0));
+    }
+    let total = lengths[lengths.len() - 1];
+    let last = (points.len() - 1) as f64;
+    lengths
+        .iter()
+        .enumerate()
+        .map(|(i, length)| {
+            if total < f64::EPSILON {
+                i as f64 / last // No length to go by, so spread evenly
+            } else {
+                length / total
+            }
+        })
+        .collect()
+}
+
+fn point_at(points: &[Vertex], fractions: &[f64], f: f64) -> [f64; 2] {
+    let last = points.len() - 1;
+    if f <= 0.0 {
+        return points[0].position;
+    }
+    if f >= 1.0 {
+        return points[last].position;
+    }
+    let i = fractions[1..]
+        .iter()
+        .position(|&end| end >= f)
+        .unwrap_or(last - 1);
+    let (start, end) = (fractions[i], fractions[i + 1]);
+    let u = if (end - start).abs() < f64::EPSILON {
+        0.0
+    } else {
+        (f - start) / (end - start)
+    };
+    let ([x0, y0], [x1, y1]) = (points[i].position, points[i + 1].position);
+    [x0 + u * (x1 - x0), y0 + u * (y1 - y0)]
+}
+
+// Matching polylines cut into the same number of two point lines. Both are cut at every
+// corner of either one, at the same fraction of their length, so the pieces correspond
+pub fn split_pair(src: &[Vertex], dst: &[Vertex]) -> (LineSet, LineSet) {
+    if src.len() < 2 || dst.len() < 2 {
+        return (Vec::new(), Vec::new());
+    }
+    if src.len() == 2 && dst.len() == 2 {
+        return (vec![src.to_vec()], vec![dst.to_vec()]);
+    }
+    let (src_fractions, dst_fractions) = (fractions(src), fractions(dst));
+    let mut cuts: Vec<f64> = src_fractions
+        .iter()
+        .chain(dst_fractions.iter())
+        .cloned()
+        .collect();
+    cuts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    cuts.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+
+    let pieces = |points: &[Vertex], fractions: &[f64]| -> LineSet {
+        cuts.windows(2)
+            .map(|pair| {
+                pair.iter()
+                    .map(|&f| Vertex {
+                        position: point_at(points, fractions, f),
+                    })
+                    .collect()
+            })
+            .collect()
+    };
+    (pieces(src, &src_fractions), pieces(dst, &dst_fractions))
+}
+
+// Every pair of features split into the straight lines Morph works with
+pub fn split_lines(src_lines: &[Vec<Vertex>], dst_lines: &[Vec<Vertex>]) -> (LineSet, LineSet) {
+    let mut split = (Vec::new(), Vec::new());
+    for (src, dst) in src_lines.iter().zip(dst_lines.iter()) {
+        let (src_pieces, dst_pieces) = split_pair(src, dst);
+        split.0.extend(src_pieces);
+        split.1.extend(dst_pieces);
+    }
+    split
+}
+
+// One timing per feature, repeated for each straight line split_lines cuts that feature into,
+// so Morph::with_timings moves every piece of a curve together. Features past the end of
+// timings follow t, like lines without a timing
+pub fn split_timings(
+    src_lines: &[Vec<Vertex>],
+    dst_lines: &[Vec<Vertex>],
+    timings: &[LineTiming],
+) -> Vec<LineTiming> {
+    let mut split = Vec::new();
+    for (i, (src, dst)) in src_lines.iter().zip(dst_lines.iter()).enumerate() {
+        let timing = timings.get(i).cloned().unwrap_or_default();
+        let pieces = split_pair(src, dst).0.len();
+        split.extend(std::iter::repeat(timing).take(pieces));
+    }
+    split
+}
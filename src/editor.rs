@@ -0,0 +1,55 @@
+use crate::Side;
+
+// What the single editor window is doing, each carrying the image to return to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EditorState {
+    Editing(Side),
+    Preview(Side),
+    Rendering(Side),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EditorEvent {
+    SwitchImage,
+    TogglePreview,
+    StartRender,
+    RenderFinished,
+    CancelRender,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        EditorState::Editing(Side::Src)
+    }
+}
+
+impl EditorState {
+    // Image whose lines are being edited, if any
+    pub fn editing(self) -> Option<Side> {
+        match self {
+            EditorState::Editing(side) => Some(side),
+            _ => None,
+        }
+    }
+
+    // State after the event, events that don't apply leave it unchanged
+    pub fn next(self, event: EditorEvent) -> EditorState {
+        match (self, event) {
+            (EditorState::Editing(side), EditorEvent::SwitchImage) => {
+                EditorState::Editing(side.other())
+            }
+            (EditorState::Editing(side), EditorEvent::TogglePreview) => EditorState::Preview(side),
+            (EditorState::Preview(side), EditorEvent::TogglePreview)
+            | (EditorState::Preview(side), EditorEvent::SwitchImage) => EditorState::Editing(side),
+            (EditorState::Editing(side), EditorEvent::StartRender)
+            | (EditorState::Preview(side), EditorEvent::StartRender) => {
+                EditorState::Rendering(side)
+            }
+            (EditorState::Rendering(side), EditorEvent::RenderFinished) => {
+                EditorState::Preview(side)
+            }
+            (EditorState::Rendering(side), EditorEvent::CancelRender) => EditorState::Editing(side),
+            (state, _) => state,
+        }
+    }
+}
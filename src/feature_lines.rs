@@ -0,0 +1,313 @@
+use crate::curves::{segment_distance, split_lines, split_timings};
+use crate::{LineTiming, Vertex};
+
+pub type LineSet = Vec<Vec<Vertex>>;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Side {
+    Src,
+    Dst,
+}
+
+impl Side {
+    pub fn other(self) -> Side {
+        match self {
+            Side::Src => Side::Dst,
+            Side::Dst => Side::Src,
+        }
+    }
+}
+
+// One feature as drawn on each image, either of which may still be missing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinePair {
+    pub src: Option<Vec<Vertex>>,
+    pub dst: Option<Vec<Vertex>>,
+    pub timing: Option<LineTiming>, // When the feature moves, None to follow t
+}
+
+impl LinePair {
+    pub fn get(&self, side: Side) -> Option<&Vec<Vertex>> {
+        match side {
+            Side::Src => self.src.as_ref(),
+            Side::Dst => self.dst.as_ref(),
+        }
+    }
+
+    fn slot(&mut self, side: Side) -> &mut Option<Vec<Vertex>> {
+        match side {
+            Side::Src => &mut self.src,
+            Side::Dst => &mut self.dst,
+        }
+    }
+}
+
+// Every change is stored with enough information to reverse it
+#[derive(Clone, Debug)]
+enum Edit {
+    Insert {
+        index: usize,
+        pair: LinePair,
+    },
+    Remove {
+        index: usize,
+        pair: LinePair,
+    },
+    Replace {
+        index: usize,
+        side: Side,
+        old: Option<Vec<Vertex>>,
+        new: Option<Vec<Vertex>>,
+    },
+    Retime {
+        index: usize,
+        old: Option<LineTiming>,
+        new: Option<LineTiming>,
+    },
+}
+
+impl Edit {
+    fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::Insert { index, pair } => Edit::Remove { index, pair },
+            Edit::Remove { index, pair } => Edit::Insert { index, pair },
+            Edit::Replace {
+                index,
+                side,
+                old,
+                new,
+            } => Edit::Replace {
+                index,
+                side,
+                old: new,
+                new: old,
+            },
+            Edit::Retime { index, old, new } => Edit::Retime {
+                index,
+                old: new,
+                new: old,
+            },
+        }
+    }
+}
+
+// Feature lines of both images, paired by index, with undo and redo of every change
+#[derive(Clone, Debug, Default)]
+pub struct FeatureLines {
+    pairs: Vec<LinePair>,
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+}
+
+impl FeatureLines {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn pairs(&self) -> &[LinePair] {
+        &self.pairs
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    // Lines drawn on one image with the index of the pair each belongs to
+    pub fn lines(&self, side: Side) -> Vec<(usize, &Vec<Vertex>)> {
+        self.pairs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pair)| pair.get(side).map(|line| (i, line)))
+            .collect()
+    }
+
+    // Source and destination lines ready for Morph, or None while any pair is incomplete.
+    // Polylines and curves come split into matching straight pieces
+    pub fn complete_lines(&self) -> Option<(LineSet, LineSet)> {
+        let mut src_lines = Vec::new();
+        let mut dst_lines = Vec::new();
+        for pair in &self.pairs {
+            src_lines.push(pair.src.clone()?);
+            dst_lines.push(pair.dst.clone()?);
+        }
+        Some(split_lines(&src_lines, &dst_lines))
+    }
+
+    // Source and destination lines of the pairs drawn on both images so far, split like complete_lines
+    pub fn matched_lines(&self) -> (LineSet, LineSet) {
+        let (src_lines, dst_lines): (LineSet, LineSet) = self
+            .pairs
+            .iter()
+            .filter_map(|pair| Some((pair.src.clone()?, pair.dst.clone()?)))
+            .unzip();
+        split_lines(&src_lines, &dst_lines)
+    }
+
+    // One timing per line of matched_lines, so also of complete_lines once every pair is complete
+    pub fn matched_timings(&self) -> Vec<LineTiming> {
+        let mut src_lines = Vec::new();
+        let mut dst_lines = Vec::new();
+        let mut timings = Vec::new();
+        for pair in &self.pairs {
+            if let (Some(src), Some(dst)) = (&pair.src, &pair.dst) {
+                src_lines.push(src.clone());
+                dst_lines.push(dst.clone());
+                timings.push(pair.timing.unwrap_or_default());
+            }
+        }
+        split_timings(&src_lines, &dst_lines, &timings)
+    }
+
+    // Pair a new line will join: the first one still missing that side, else a new pair
+    pub fn next_index(&self, side: Side) -> usize {
+        self.pairs
+            .iter()
+            .position(|pair| pair.get(side).is_none())
+            .unwrap_or(self.pairs.len())
+    }
+
+    pub fn add_line(&mut self, side: Side, line: Vec<Vertex>) -> usize {
+        let index = self.next_index(side);
+        if index == self.pairs.len() {
+            let mut pair = LinePair::default();
+            *pair.slot(side) = Some(line);
+            self.push(Edit::Insert { index, pair });
+        } else {
+            self.push(Edit::Replace {
+                index,
+                side,
+                old: None,
+                new: Some(line),
+            });
+        }
+        index
+    }
+
+    pub fn add_pair(&mut self, src: Vec<Vertex>, dst: Vec<Vertex>) -> usize {
+        let index = self.pairs.len();
+        let pair = LinePair {
+            src: Some(src),
+            dst: Some(dst),
+            timing: None,
+        };
+        self.push(Edit::Insert { index, pair });
+        index
+    }
+
+    pub fn move_endpoint(&mut self, side: Side, index: usize, endpoint: usize, to: [f64; 2]) {
+        let old = match self.pairs.get(index).and_then(|pair| pair.get(side)) {
+            Some(line) => line.clone(),
+            None => return,
+        };
+        let mut new = old.clone();
+        match new.get_mut(endpoint) {
+            Some(vertex) => vertex.position = to,
+            None => return,
+        }
+        self.push(Edit::Replace {
+            index,
+            side,
+            old: Some(old),
+            new: Some(new),
+        });
+    }
+
+    pub fn set_timing(&mut self, index: usize, timing: Option<LineTiming>) {
+        let old = match self.pairs.get(index) {
+            Some(pair) => pair.timing,
+            None => return,
+        };
+        self.push(Edit::Retime {
+            index,
+            old,
+            new: timing,
+        });
+    }
+
+    // Removes the line from both images
+    pub fn delete(&mut self, index: usize) {
+        if index < self.pairs.len() {
+            let pair = self.pairs[index].clone();
+            self.push(Edit::Remove { index, pair });
+        }
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some(edit) => {
+                self.apply(&edit.inverse());
+                self.redo_stack.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        match self.redo_stack.pop() {
+            Some(edit) => {
+                self.apply(&edit);
+                self.undo_stack.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Closest endpoint within radius as (pair index, endpoint index)
+    pub fn pick_endpoint(
+        &self,
+        side: Side,
+        point: [f64; 2],
+        radius: f64,
+    ) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize, f64)> = None;
+        for (i, line) in self.lines(side) {
+            for (e, v) in line.iter().enumerate() {
+                let dist = (v.position[0] - point[0]).hypot(v.position[1] - point[1]);
+                if dist <= radius && best.map_or(true, |(_, _, d)| dist < d) {
+                    best = Some((i, e, dist));
+                }
+            }
+        }
+        best.map(|(i, e, _)| (i, e))
+    }
+
+    // Closest line within radius of the point, measured to its nearest segment
+    pub fn pick_line(&self, side: Side, point: [f64; 2], radius: f64) -> Option<usize> {
+        let mut best: Option<(usize, f64)> = None;
+        for (i, line) in self.lines(side) {
+            let dist = line
+                .windows(2)
+                .map(|pair| segment_distance(pair[0].position, pair[1].position, point))
+                .fold(f64::INFINITY, f64::min);
+            if dist <= radius && best.map_or(true, |(_, d)| dist < d) {
+                best = Some((i, dist));
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+
+    fn push(&mut self, edit: Edit) {
+        self.apply(&edit);
+        self.undo_stack.push(edit);
+        self.redo_stack.clear();
+    }
+
+    fn apply(&mut self, edit: &Edit) {
+        match edit.clone() {
+            Edit::Insert { index, pair } => self.pairs.insert(index, pair),
+            Edit::Remove { index, .. } => {
+                self.pairs.remove(index);
+            }
+            Edit::Replace {
+                index, side, new, ..
+            } => *self.pairs[index].slot(side) = new,
+            Edit::Retime { index, new, .. } => self.pairs[index].timing = new,
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use image::png::PNGEncoder;
+use image::{ColorType, ImageBuffer, Rgba, RgbaImage};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::{BlendSpace, Morph, Side};
+
+const FLO_TAG: f32 = 202_021.25; // "PIEH" read as a little endian float
+const FLO_UNKNOWN: f32 = 1e9; // Flow at or above this marks an unknown vector
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325; // 64 bit FNV-1a, for cache keys
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// TIFF tags and field types used by the STMap writer and reader
+const TIFF_WIDTH: u16 = 256;
+const TIFF_HEIGHT: u16 = 257;
+const TIFF_BITS_PER_SAMPLE: u16 = 258;
+const TIFF_COMPRESSION: u16 = 259;
+const TIFF_PHOTOMETRIC: u16 = 262;
+const TIFF_STRIP_OFFSETS: u16 = 273;
+const TIFF_SAMPLES_PER_PIXEL: u16 = 277;
+const TIFF_ROWS_PER_STRIP: u16 = 278;
+const TIFF_STRIP_BYTE_COUNTS: u16 = 279;
+const TIFF_PLANAR_CONFIG: u16 = 284;
+const TIFF_SAMPLE_FORMAT: u16 = 339;
+const TIFF_SHORT: u16 = 3;
+const TIFF_LONG: u16 = 4;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Size of a field whose dimensions come from a file header, which may be anything
+fn pixel_count(width: u32, height: u32) -> io::Result<usize> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| invalid("dimensions too large"))
+}
+
+// For every output pixel, the position in the input image it takes its colour from, as
+// Morph::warp finds it. Positions are in input pixels with (0, 0) the top left pixel
+#[derive(Clone, Debug, PartialEq)]
+pub struct WarpField {
+    pub width: u32,
+    pub height: u32,
+    pub positions: Vec<[f64; 2]>, // Row by row from the top
+}
+
+impl WarpField {
+    // Every pixel sampling itself
+    pub fn identity(width: u32, height: u32) -> Self {
+        let positions = (0..height)
+            .flat_map(|y| (0..width).map(move |x| [x as f64, y as f64]))
+            .collect();
+        WarpField {
+            width,
+            height,
+            positions,
+        }
+    }
+
+    // The mapping warp_image uses for one side of the morph
+    pub fn from_morph(morph: &Morph, side: Side) -> Self {
+        let (img, img_lines) = match side {
+            Side::Src => (morph.src, morph.src_lines),
+            Side::Dst => (morph.dst, morph.dst_lines),
+        };
+        let (width, height) = img.dimensions();
+        let inter_lines = morph.interpolate_lines();
+        let positions = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (img_x, img_y) = morph.warp_point(x as f64, y as f64, &inter_lines, img_lines);
+                [img_x, img_y]
+            })
+            .collect();
+        WarpField {
+            width,
+            height,
+            positions,
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> [f64; 2] {
+        self.positions[(y * self.width + x) as usize]
+    }
+
+    // Resamples img through the field the way Morph::warp_image does, nearest pixel and
+    // transparent where the position falls off the image
+    pub fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        let mut warped: RgbaImage = ImageBuffer::new(self.width, self.height);
+        if width == 0 || height == 0 {
+            return warped;
+        }
+        for y in 0..self.height.saturating_sub(1) {
+            for x in 0..self.width.saturating_sub(1) {
+                let [img_x, img_y] = self.get(x, y);
+                let img_x = img_x.clamp(0.0, (width - 1) as f64);
+                let img_y = img_y.clamp(0.0, (height - 1) as f64);
+                if img_x > 0.0
+                    && img_y > 0.0
+                    && (img_x as u32) < width - 1
+                    && (img_y as u32) < height - 1
+                {
+                    warped.put_pixel(x, y, *img.get_pixel(img_x as u32, img_y as u32));
+                }
+            }
+        }
+        warped
+    }
+
+    // Resamples img through the field blending the four nearest pixels, so positions between
+    // pixels give smooth results. Like apply, img may be any size and positions off it come
+    // out transparent, though it is normally the size the field was made for
+    pub fn sample(&self, img: &RgbaImage) -> RgbaImage {
+        self.sample_in(img, BlendSpace::Srgb)
+    }
+
+    // sample weighting the pixels' colours in space, e.g. in linear light for photographs
+    pub fn sample_in(&self, img: &RgbaImage, space: BlendSpace) -> RgbaImage {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let [img_x, img_y] = self.get(x, y);
+            bilinear(img, img_x, img_y, space)
+        })
+    }
+
+    // Identifies everything the field for one side of a morph depends on: the image size, both
+    // sets of lines, the intermediate lines, the parameters, the line mode and the timings.
+    // Hashed with FNV-1a over fixed width values, so keys stay the same across Rust versions
+    pub fn cache_key(morph: &Morph, side: Side) -> u64 {
+        let mut key = FNV_OFFSET;
+        let mut hash = |value: u64| {
+            for byte in value.to_le_bytes().iter() {
+                key = (key ^ *byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+        let img = match side {
+            Side::Src => morph.src,
+            Side::Dst => morph.dst,
+        };
+        hash(side as u64);
+        hash(img.width() as u64);
+        hash(img.height() as u64);
+        let lines = [
+            Some(morph.src_lines),
+            Some(morph.dst_lines),
+            morph.inter_lines,
+        ];
+        for lines in lines.iter() {
+            // Counts are offset by one so a missing set differs from an empty one
+            hash(lines.map_or(0, |lines| lines.len() as u64 + 1));
+            for line in lines.iter().flat_map(|lines| lines.iter()) {
+                hash(line.len() as u64);
+                for vertex in line {
+                    hash(vertex.position[0].to_bits());
+                    hash(vertex.position[1].to_bits());
+                }
+            }
+        }
+        for value in &[morph.t, morph.p, morph.a, morph.b] {
+            hash(value.to_bits());
+        }
+        hash(morph.line_mode as u64);
+        hash(morph.timings.map_or(0, |timings| timings.len() as u64 + 1));
+        for timing in morph.timings.iter().flat_map(|timings| timings.iter()) {
+            hash(timing.start.to_bits());
+            hash(timing.end.to_bits());
+            hash(timing.easing as u64);
+        }
+        key
+    }
+
+    // The field for one side of a morph, read from dir when an earlier call saved it there and
+    // otherwise computed and saved as a .flo file named after its cache key. A saved file that
+    // can't be read or doesn't fit the image is computed and saved again. Either way the
+    // positions are those stored in the file, so results don't depend on what was cached
+    pub fn cached<P: AsRef<Path>>(morph: &Morph, side: Side, dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let key = WarpField::cache_key(morph, side);
+        let path = dir.join(format!("{:016x}.flo", key));
+        let size = match side {
+            Side::Src => morph.src.dimensions(),
+            Side::Dst => morph.dst.dimensions(),
+        };
+        if let Ok(field) = WarpField::read_flo(&path) {
+            if (field.width, field.height) == size {
+                return Ok(field);
+            }
+        }
+        let field = WarpField::from_morph(morph, side).flo_rounded();
+        std::fs::create_dir_all(dir)?;
+
+        // Written beside the entry and renamed over it, so an interrupted write never leaves a
+        // truncated entry behind
+        let partial = dir.join(format!("{:016x}.{}.partial", key, std::process::id()));
+        let saved = field
+            .write_flo(&partial)
+            .and_then(|()| std::fs::rename(&partial, &path));
+        if saved.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        saved.map(|()| field)
+    }
+
+    // Middlebury optical flow: the offset from each output pixel to where it samples
+    pub fn write_flo<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&FLO_TAG.to_le_bytes())?;
+        out.write_all(&(self.width as i32).to_le_bytes())?;
+        out.write_all(&(self.height as i32).to_le_bytes())?;
+        for (i, [px, py]) in self.positions.iter().enumerate() {
+            let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+            out.write_all(&((px - x as f64) as f32).to_le_bytes())?;
+            out.write_all(&((py - y as f64) as f32).to_le_bytes())?;
+        }
+        out.flush()
+    }
+
+    // The field as write_flo and read_flo bring it back, with the offsets rounded to f32
+    fn flo_rounded(self) -> Self {
+        let width = self.width;
+        let positions = self
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, [px, py])| {
+                let (x, y) = ((i as u32 % width) as f64, (i as u32 / width) as f64);
+                flo_position(x, y, (px - x) as f32, (py - y) as f32)
+            })
+            .collect();
+        WarpField { positions, ..self }
+    }
+
+    // Unknown vectors leave the pixel where it is
+    pub fn read_flo<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        let word = |i: usize| -> io::Result<[u8; 4]> {
+            let mut word = [0; 4];
+            word.copy_from_slice(
+                bytes
+                    .get(i * 4..i * 4 + 4)
+                    .ok_or_else(|| invalid("flo file too short"))?,
+            );
+            Ok(word)
+        };
+        if f32::from_le_bytes(word(0)?) != FLO_TAG {
+            return Err(invalid("not a flo file"));
+        }
+        let (width, height) = (i32::from_le_bytes(word(1)?), i32::from_le_bytes(word(2)?));
+        if width <= 0 || height <= 0 {
+            return Err(invalid("bad flo dimensions"));
+        }
+        let (width, height) = (width as u32, height as u32);
+        let pixels = pixel_count(width, height)?;
+        let size = pixels.checked_mul(8).and_then(|size| size.checked_add(12));
+        if size.map_or(true, |size| bytes.len() < size) {
+            return Err(invalid("flo file too short"));
+        }
+        let mut positions = Vec::with_capacity(pixels);
+        for i in 0..pixels {
+            let (x, y) = ((i % width as usize) as f64, (i / width as usize) as f64);
+            let (u, v) = (
+                f32::from_le_bytes(word(3 + 2 * i)?),
+                f32::from_le_bytes(word(4 + 2 * i)?),
+            );
+            positions.push(flo_position(x, y, u, v));
+        }
+        Ok(WarpField {
+            width,
+            height,
+            positions,
+        })
+    }
+
+    // STMap coordinates as compositing packages use them: the pixel centre's position across
+    // the input from 0 to 1, with v running bottom up
+    fn to_uv(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        [
+            (x + 0.5) / self.width as f64,
+            1.0 - (y + 0.5) / self.height as f64,
+        ]
+    }
+
+    fn from_uv(width: u32, height: u32, uvs: &[[f64; 2]]) -> Self {
+        let positions = uvs
+            .iter()
+            .map(|[u, v]| [u * width as f64 - 0.5, (1.0 - v) * height as f64 - 0.5])
+            .collect();
+        WarpField {
+            width,
+            height,
+            positions,
+        }
+    }
+
+    // STMap as a 16 bit RGB PNG, blue unused. Positions off the image are clamped to its edges
+    pub fn write_stmap_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut data = Vec::with_capacity(self.positions.len() * 6);
+        for &position in &self.positions {
+            let [u, v] = self.to_uv(position);
+            for &c in &[u, v, 0.0] {
+                let c = (c.clamp(0.0, 1.0) * 65535.0).round() as u16;
+                data.extend_from_slice(&c.to_be_bytes());
+            }
+        }
+        let out = BufWriter::new(File::create(path)?);
+        PNGEncoder::new(out).encode(&data, self.width, self.height, ColorType::RGB(16))
+    }
+
+    pub fn read_stmap_png<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        // Without transformations the png crate keeps all 16 bits, which image's decoder drops
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let (info, mut reader) = decoder.read_info().map_err(io::Error::from)?;
+        let channels = match (info.color_type, info.bit_depth) {
+            (png::ColorType::RGB, png::BitDepth::Sixteen) => 3,
+            (png::ColorType::RGBA, png::BitDepth::Sixteen) => 4,
+            _ => return Err(invalid("STMap PNGs must be 16 bit RGB")),
+        };
+        let (width, height) = (info.width, info.height);
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).map_err(io::Error::from)?;
+        let uvs: Vec<[f64; 2]> = data
+            .chunks(2 * channels)
+            .map(|pixel| {
+                let c = |i: usize| {
+                    u16::from_be_bytes([pixel[2 * i], pixel[2 * i + 1]]) as f64 / 65535.0
+                };
+                [c(0), c(1)]
+            })
+            .collect();
+        Ok(WarpField::from_uv(width, height, &uvs))
+    }
+
+    // STMap as an uncompressed 32 bit float RGB TIFF, blue unused, which keeps positions off the image
+    pub fn write_stmap_tiff<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut pixels = Vec::with_capacity(self.positions.len() * 12);
+        for &position in &self.positions {
+            let [u, v] = self.to_uv(position);
+            for &c in &[u as f32, v as f32, 0.0] {
+                pixels.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+
+        // Header, pixels in one strip, the three bits per sample values, then the directory
+        let bits_offset = 8 + pixels.len() as u32;
+        let formats_offset = bits_offset + 6;
+        let directory_offset = formats_offset + 6;
+        let entries: [(u16, u16, u32, u32); 11] = [
+            (TIFF_WIDTH, TIFF_LONG, 1, self.width),
+            (TIFF_HEIGHT, TIFF_LONG, 1, self.height),
+            (TIFF_BITS_PER_SAMPLE, TIFF_SHORT, 3, bits_offset),
+            (TIFF_COMPRESSION, TIFF_SHORT, 1, 1),
+            (TIFF_PHOTOMETRIC, TIFF_SHORT, 1, 2),
+            (TIFF_STRIP_OFFSETS, TIFF_LONG, 1, 8),
+            (TIFF_SAMPLES_PER_PIXEL, TIFF_SHORT, 1, 3),
+            (TIFF_ROWS_PER_STRIP, TIFF_LONG, 1, self.height),
+            (TIFF_STRIP_BYTE_COUNTS, TIFF_LONG, 1, pixels.len() as u32),
+            (TIFF_PLANAR_CONFIG, TIFF_SHORT, 1, 1),
+            (TIFF_SAMPLE_FORMAT, TIFF_SHORT, 3, formats_offset),
+        ];
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"II")?;
+        out.write_all(&42u16.to_le_bytes())?;
+        out.write_all(&directory_offset.to_le_bytes())?;
+        out.write_all(&pixels)?;
+        for _ in 0..3 {
+            out.write_all(&32u16.to_le_bytes())?;
+        }
+        for _ in 0..3 {
+            out.write_all(&3u16.to_le_bytes())?; // IEEE floating point
+        }
+        out.write_all(&(entries.len() as u16).to_le_bytes())?;
+        for &(tag, kind, count, value) in &entries {
+            out.write_all(&tag.to_le_bytes())?;
+            out.write_all(&kind.to_le_bytes())?;
+            out.write_all(&count.to_le_bytes())?;
+            if kind == TIFF_SHORT && count == 1 {
+                out.write_all(&(value as u16).to_le_bytes())?;
+                out.write_all(&[0, 0])?;
+            } else {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        out.write_all(&0u32.to_le_bytes())?; // No further directories
+        out.flush()
+    }
+
+    // Reads uncompressed, interleaved 32 bit float TIFFs with two or more samples per pixel
+    // in either byte order, such as those write_stmap_tiff makes
+    pub fn read_stmap_tiff<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        let tiff = Tiff::new(&bytes)?;
+        let directory = tiff.u32(4)? as usize;
+        let mut tags = std::collections::HashMap::new();
+        for i in 0..tiff.u16(directory)? as usize {
+            let entry = directory + 2 + 12 * i;
+            tags.insert(tiff.u16(entry)?, entry);
+        }
+        let tag = |tag: u16| -> io::Result<Vec<u32>> {
+            let entry = *tags.get(&tag).ok_or_else(|| invalid("TIFF tag missing"))?;
+            tiff.values(entry)
+        };
+        let optional = |name: u16, default: u32| -> io::Result<u32> {
+            Ok(if tags.contains_key(&name) {
+                tag(name)?[0]
+            } else {
+                default
+            })
+        };
+
+        let (width, height) = (tag(TIFF_WIDTH)?[0], tag(TIFF_HEIGHT)?[0]);
+        let samples = optional(TIFF_SAMPLES_PER_PIXEL, 1)? as usize;
+        if optional(TIFF_COMPRESSION, 1)? != 1
+            || optional(TIFF_PLANAR_CONFIG, 1)? != 1
+            || samples < 2
+            || tag(TIFF_BITS_PER_SAMPLE)?.iter().any(|&bits| bits != 32)
+            || tag(TIFF_SAMPLE_FORMAT)?.iter().any(|&format| format != 3)
+        {
+            return Err(invalid("STMap TIFFs must be uncompressed 32 bit float"));
+        }
+
+        let pixels = pixel_count(width, height)?;
+        let size = pixels
+            .checked_mul(samples)
+            .and_then(|size| size.checked_mul(4))
+            .ok_or_else(|| invalid("dimensions too large"))?;
+        // Only as much as the strips hold, however large the header says the image is
+        let mut data = Vec::new();
+        for (offset, count) in tag(TIFF_STRIP_OFFSETS)?
+            .iter()
+            .zip(tag(TIFF_STRIP_BYTE_COUNTS)?.iter())
+        {
+            let end = offset
+                .checked_add(*count)
+                .ok_or_else(|| invalid("TIFF strip out of range"))?;
+            data.extend_from_slice(
+                bytes
+                    .get(*offset as usize..end as usize)
+                    .ok_or_else(|| invalid("TIFF strip out of range"))?,
+            );
+        }
+        if data.len() < size {
+            return Err(invalid("TIFF too short"));
+        }
+        let uvs: Vec<[f64; 2]> = (0..pixels)
+            .map(|i| {
+                let c = |s: usize| tiff.f32_from(&data[(i * samples + s) * 4..]) as f64;
+                [c(0), c(1)]
+            })
+            .collect();
+        Ok(WarpField::from_uv(width, height, &uvs))
+    }
+}
+
+// Where a .flo offset from (x, y) lands, or (x, y) itself for an unknown vector
+fn flo_position(x: f64, y: f64, u: f32, v: f32) -> [f64; 2] {
+    if u.abs() >= FLO_UNKNOWN || v.abs() >= FLO_UNKNOWN {
+        [x, y]
+    } else {
+        [x + u as f64, y + v as f64]
+    }
+}
+
+// Colour at a position between pixels, weighting the four around it by their alpha so
+// transparent neighbours don't darken the edges. Transparent off the image
+fn bilinear(img: &RgbaImage, x: f64, y: f64, space: BlendSpace) -> Rgba<u8> {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    if !(x >= 0.0 && y >= 0.0 && x <= (width - 1) as f64 && y <= (height - 1) as f64) {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let mut sum = [0.0; 4];
+    for &(px, py, weight) in &[
+        (x0, y0, (1.0 - fx) * (1.0 - fy)),
+        (x1, y0, fx * (1.0 - fy)),
+        (x0, y1, (1.0 - fx) * fy),
+        (x1, y1, fx * fy),
+    ] {
+        let Rgba([r, g, b, a]) = *img.get_pixel(px, py);
+        let alpha = weight * a as f64;
+        let decoded = space.decode([r as f64, g as f64, b as f64]);
+        for c in 0..3 {
+            sum[c] += alpha * decoded[c];
+        }
+        sum[3] += alpha;
+    }
+    if sum[3] <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let rgb = space.encode([sum[0] / sum[3], sum[1] / sum[3], sum[2] / sum[3]]);
+    let channel = |c: f64| c.round().clamp(0.0, 255.0) as u8;
+    Rgba([
+        channel(rgb[0]),
+        channel(rgb[1]),
+        channel(rgb[2]),
+        channel(sum[3]),
+    ])
+}
+
+// Byte order aware reads from a TIFF file
+struct Tiff<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        let little_endian = match bytes.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(invalid("not a TIFF file")),
+        };
+        Ok(Tiff {
+            bytes,
+            little_endian,
+        })
+    }
+
+    fn u16(&self, at: usize) -> io::Result<u16> {
+        let b = self
+            .bytes
+            .get(at..at + 2)
+            .ok_or_else(|| invalid("TIFF too short"))?;
+        let b = [b[0], b[1]];
+        Ok(if self.little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    fn u32(&self, at: usize) -> io::Result<u32> {
+        let b = self
+            .bytes
+            .get(at..at + 4)
+            .ok_or_else(|| invalid("TIFF too short"))?;
+        Ok(self.u32_from(b))
+    }
+
+    fn u32_from(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        }
+    }
+
+    fn f32_from(&self, b: &[u8]) -> f32 {
+        f32::from_bits(self.u32_from(b))
+    }
+
+    // Short or long values of a directory entry, stored in the entry when they fit in four bytes
+    fn values(&self, entry: usize) -> io::Result<Vec<u32>> {
+        let (kind, count) = (self.u16(entry + 2)?, self.u32(entry + 4)? as usize);
+        let size: usize = match kind {
+            TIFF_SHORT => 2,
+            TIFF_LONG => 4,
+            _ => return Err(invalid("unsupported TIFF field type")),
+        };
+        let length = size
+            .checked_mul(count)
+            .filter(|&length| length <= self.bytes.len())
+            .ok_or_else(|| invalid("TIFF too short"))?;
+        let start = if length <= 4 {
+            entry + 8
+        } else {
+            self.u32(entry + 8)? as usize
+        };
+        (0..count)
+            .map(|i| match size {
+                2 => self.u16(start + 2 * i).map(u32::from),
+                _ => self.u32(start + 4 * i),
+            })
+            .collect()
+    }
+}
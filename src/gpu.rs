@@ -0,0 +1,382 @@
+use glium::backend::Facade;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::program::ProgramCreationInput;
+use glium::texture::{MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler};
+use glium::vertex::EmptyVertexAttributes;
+use glium::{DrawParameters, Program, ProgramCreationError, Rect, Surface};
+use image::RgbaImage;
+
+use crate::{BlendSpace, LineInterpolation, LineTiming, Vertex};
+
+// Covers the viewport with two triangles, no vertex data needed
+const VERTEX_SHADER: &str = r#"
+    #version 140
+
+    void main() {
+      vec2 corner = vec2(gl_VertexID % 2, gl_VertexID / 2);
+      gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+    }
+"#;
+
+// Same steps as Morph::warp_image and Morph::blend, evaluated for the pixel under each fragment
+const FRAGMENT_SHADER: &str = r#"
+    #version 140
+
+    uniform sampler2D src;
+    uniform sampler2D dst;
+    uniform sampler2D lines; // Rows of source lines as (P, Q), destination lines and line t
+    uniform int line_count;
+    uniform float t;
+    uniform float p;
+    uniform float a;
+    uniform float b;
+    uniform vec4 viewport; // Left, bottom, width and height in framebuffer pixels
+    uniform vec2 image_size;
+    uniform bool rotate_lines; // LineInterpolation::CentreAngleLength rather than Endpoints
+    uniform int blend_space; // BlendSpace: 0 Srgb, 1 Linear, 2 Oklab, 3 Lab
+    out vec4 color;
+
+    const float PI = 3.14159265358979;
+
+    // Same guard as extrapolated_line in the CPU version, 0.25 being MIN_EXTRAPOLATED_LENGTH
+    vec4 extrapolated(vec4 line, vec4 nearest) {
+      vec2 near = nearest.zw - nearest.xy;
+      float near_len = length(near);
+      if (near_len < 1e-12) {
+        return line;
+      }
+      vec2 dir = near / near_len;
+      float min_len = 0.25 * near_len;
+      if (dot(line.zw - line.xy, dir) >= min_len) {
+        return line;
+      }
+      vec2 mid = (line.xy + line.zw) / 2.0;
+      vec2 half_line = dir * min_len / 2.0;
+      return vec4(mid - half_line, mid + half_line);
+    }
+
+    // Same as rotated_line in the CPU version
+    vec4 rotated(vec4 src_line, vec4 dst_line, float t) {
+      vec2 src_d = src_line.zw - src_line.xy;
+      vec2 dst_d = dst_line.zw - dst_line.xy;
+      float src_angle = atan(src_d.y, src_d.x);
+      float turn = mod(atan(dst_d.y, dst_d.x) - src_angle + PI, 2.0 * PI) - PI;
+      float angle = src_angle + t * turn;
+      float half_len = mix(length(src_d), length(dst_d), t) / 2.0;
+      if (t < 0.0) {
+        half_len = max(half_len, 0.25 * length(src_d) / 2.0);
+      } else if (t > 1.0) {
+        half_len = max(half_len, 0.25 * length(dst_d) / 2.0);
+      }
+      vec2 centre = mix(src_line.xy + src_line.zw, dst_line.xy + dst_line.zw, t) / 2.0;
+      vec2 d = vec2(cos(angle), sin(angle)) * half_len;
+      return vec4(centre - d, centre + d);
+    }
+
+    vec2 warp(vec2 x, int row) {
+      vec2 d_sum = vec2(0.0);
+      float weight_sum = 0.0;
+      for (int i = 0; i < line_count; i++) {
+        vec4 src_line = texelFetch(lines, ivec2(i, 0), 0);
+        vec4 dst_line = texelFetch(lines, ivec2(i, 1), 0);
+        float line_t = texelFetch(lines, ivec2(i, 2), 0).x;
+        vec4 inter = mix(src_line, dst_line, line_t);
+        if (rotate_lines) {
+          inter = rotated(src_line, dst_line, line_t);
+        } else if (line_t < 0.0) {
+          inter = extrapolated(inter, src_line);
+        } else if (line_t > 1.0) {
+          inter = extrapolated(inter, dst_line);
+        }
+        vec4 img = row == 0 ? src_line : dst_line;
+
+        vec2 pd = x - inter.xy;
+        vec2 pq = inter.zw - inter.xy;
+        float inter_len = dot(pq, pq);
+        if (inter_len < 1e-12) {
+          continue;
+        }
+        float u = dot(pd, pq) / inter_len;
+        inter_len = sqrt(inter_len);
+        float v = (pd.x * pq.y - pd.y * pq.x) / inter_len;
+
+        vec2 img_pq = img.zw - img.xy;
+        float img_len = length(img_pq);
+        if (img_len < 1e-12) {
+          continue;
+        }
+        vec2 xx = img.xy + u * img_pq + v * vec2(img_pq.y, -img_pq.x) / img_len;
+        float dist = u < 0.0 ? length(pd) : (u > 1.0 ? length(x - inter.zw) : abs(v));
+        float weight = pow(pow(inter_len, p) / (a + dist), b);
+        d_sum += (x - xx) * weight;
+        weight_sum += weight;
+      }
+      if (weight_sum == 0.0) {
+        return x;
+      }
+      return x - d_sum / weight_sum;
+    }
+
+    // Same conversions as color_space in the CPU version
+    vec3 to_linear(vec3 c) {
+      return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+    }
+
+    vec3 to_srgb(vec3 l) {
+      l = clamp(l, 0.0, 1.0);
+      return mix(12.92 * l, 1.055 * pow(l, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, l));
+    }
+
+    const vec3 WHITE = vec3(0.95047, 1.0, 1.08883);
+    const float LAB_DELTA = 6.0 / 29.0;
+
+    // Where the linear colour is mixed
+    vec3 coords(vec3 c) {
+      if (blend_space == 2) {
+        vec3 lms = vec3(
+          dot(vec3(0.4122214708, 0.5363325363, 0.0514459929), c),
+          dot(vec3(0.2119034982, 0.6806995451, 0.1073969566), c),
+          dot(vec3(0.0883024619, 0.2817188376, 0.6299787005), c));
+        lms = pow(lms, vec3(1.0 / 3.0));
+        return vec3(
+          dot(vec3(0.2104542553, 0.7936177850, -0.0040720468), lms),
+          dot(vec3(1.9779984951, -2.4285922050, 0.4505937099), lms),
+          dot(vec3(0.0259040371, 0.7827717662, -0.8086757660), lms));
+      }
+      if (blend_space == 3) {
+        vec3 xyz = vec3(
+          dot(vec3(0.4124564, 0.3575761, 0.1804375), c),
+          dot(vec3(0.2126729, 0.7151522, 0.0721750), c),
+          dot(vec3(0.0193339, 0.1191920, 0.9503041), c)) / WHITE;
+        vec3 f = mix(xyz / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0, pow(xyz, vec3(1.0 / 3.0)),
+                     step(LAB_DELTA * LAB_DELTA * LAB_DELTA, xyz));
+        return vec3(116.0 * f.y - 16.0, 500.0 * (f.x - f.y), 200.0 * (f.y - f.z));
+      }
+      return c;
+    }
+
+    vec3 from_coords(vec3 c) {
+      if (blend_space == 2) {
+        vec3 lms = vec3(
+          dot(vec3(1.0, 0.3963377774, 0.2158037573), c),
+          dot(vec3(1.0, -0.1055613458, -0.0638541728), c),
+          dot(vec3(1.0, -0.0894841775, -1.2914855480), c));
+        lms = lms * lms * lms;
+        return vec3(
+          dot(vec3(4.0767416621, -3.3077115913, 0.2309699292), lms),
+          dot(vec3(-1.2684380046, 2.6097574011, -0.3413193965), lms),
+          dot(vec3(-0.0041960863, -0.7034186147, 1.7076147010), lms));
+      }
+      if (blend_space == 3) {
+        float fy = (c.x + 16.0) / 116.0;
+        vec3 f = vec3(fy + c.y / 500.0, fy, fy - c.z / 200.0);
+        vec3 xyz = mix(3.0 * LAB_DELTA * LAB_DELTA * (f - 4.0 / 29.0), f * f * f,
+                       step(LAB_DELTA, f)) * WHITE;
+        return vec3(
+          dot(vec3(3.2404542, -1.5371385, -0.4985314), xyz),
+          dot(vec3(-0.9692660, 1.8760108, 0.0415560), xyz),
+          dot(vec3(0.0556434, -0.2040259, 1.0572252), xyz));
+      }
+      return c;
+    }
+
+    // Nearest pixel of the image at the warped position, transparent outside of it
+    vec4 warped(sampler2D img, vec2 x, int row) {
+      vec2 w = clamp(warp(x, row), vec2(0.0), image_size - 1.0);
+      if (w.x > 0.0 && w.y > 0.0 && w.x < image_size.x - 1.0 && w.y < image_size.y - 1.0) {
+        return texelFetch(img, ivec2(w), 0);
+      }
+      return vec4(0.0);
+    }
+
+    void main() {
+      // Image rows run top down while the framebuffer's run bottom up
+      vec2 offset = vec2(gl_FragCoord.x - viewport.x, viewport.y + viewport.w - gl_FragCoord.y);
+      vec2 x = floor(offset * image_size / viewport.zw);
+      if (x.x >= image_size.x - 1.0 || x.y >= image_size.y - 1.0) {
+        color = vec4(0.0);
+        return;
+      }
+      vec4 src_color = warped(src, x, 0);
+      vec4 dst_color = warped(dst, x, 1);
+      // Colours stop changing past t = 0 and 1, as in the CPU version
+      float u = clamp(t, 0.0, 1.0);
+      if (blend_space == 0) {
+        // Truncated to 8 bits the way the CPU version converts its colours
+        vec3 mixed = mix(src_color.rgb, dst_color.rgb, u);
+        color = vec4(floor(mixed * 255.0 + 0.001) / 255.0, 1.0);
+        return;
+      }
+      vec3 mixed = from_coords(mix(coords(to_linear(src_color.rgb)), coords(to_linear(dst_color.rgb)), u));
+      color = vec4(floor(to_srgb(mixed) * 255.0 + 0.5) / 255.0, 1.0);
+    }
+"#;
+
+// Textures are read texel by texel, so nothing may be filtered
+fn nearest(tex: &Texture2d) -> Sampler<'_, Texture2d> {
+    tex.sampled()
+        .minify_filter(MinifySamplerFilter::Nearest)
+        .magnify_filter(MagnifySamplerFilter::Nearest)
+}
+
+// Beier-Neely warp and cross dissolve run as a fragment shader, fast enough to redraw every frame
+pub struct GpuMorph {
+    program: Program,
+    pub line_mode: LineInterpolation,
+    pub blend_space: BlendSpace,
+    pub timings: Vec<LineTiming>, // As Morph::with_timings, lines past the end follow t
+}
+
+impl GpuMorph {
+    pub fn new<F: Facade>(facade: &F) -> Result<Self, ProgramCreationError> {
+        let program = Program::new(
+            facade,
+            ProgramCreationInput::SourceCode {
+                vertex_shader: VERTEX_SHADER,
+                tessellation_control_shader: None,
+                tessellation_evaluation_shader: None,
+                geometry_shader: None,
+                fragment_shader: FRAGMENT_SHADER,
+                transform_feedback_varyings: None,
+                // The shader encodes its colours like the CPU version, so they go out unchanged
+                outputs_srgb: true,
+                uses_point_size: false,
+            },
+        )?;
+        Ok(GpuMorph {
+            program,
+            line_mode: LineInterpolation::Endpoints,
+            blend_space: BlendSpace::default(),
+            timings: Vec::new(),
+        })
+    }
+
+    // Image uploaded with its top row first, as the shader reads it
+    pub fn texture<F: Facade>(facade: &F, img: &RgbaImage) -> Texture2d {
+        let raw = RawImage2d::from_raw_rgba(img.clone().into_raw(), img.dimensions());
+        Texture2d::with_format(
+            facade,
+            raw,
+            UncompressedFloatFormat::U8U8U8U8,
+            MipmapsOption::NoMipmap,
+        )
+        .unwrap()
+    }
+
+    // Draws the morph of two same sized textures over the viewport of target
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw<F: Facade, S: Surface>(
+        &self,
+        facade: &F,
+        target: &mut S,
+        viewport: Rect,
+        src: &Texture2d,
+        dst: &Texture2d,
+        src_lines: &[Vec<Vertex>],
+        dst_lines: &[Vec<Vertex>],
+        (t, p, a, b): (f64, f64, f64, f64),
+    ) {
+        let line_count = src_lines.len().min(dst_lines.len());
+        let mut data: Vec<f32> = Vec::with_capacity(12 * line_count.max(1));
+        for lines in &[src_lines, dst_lines] {
+            for line in lines.iter().take(line_count) {
+                data.extend(line[0].position.iter().map(|&c| c as f32));
+                data.extend(line[1].position.iter().map(|&c| c as f32));
+            }
+        }
+        for i in 0..line_count {
+            let line_t = self.timings.get(i).map_or(t, |timing| timing.progress(t));
+            data.extend(&[line_t as f32, 0.0, 0.0, 0.0]);
+        }
+        // Textures can't be empty, so no lines still uploads one unused texel per row
+        if line_count == 0 {
+            data = vec![0.0; 12];
+        }
+        let lines = Texture2d::with_format(
+            facade,
+            RawImage2d::from_raw_rgba(data, (line_count.max(1) as u32, 3)),
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+        )
+        .unwrap();
+
+        let (width, height) = src.dimensions();
+        let uniforms = uniform! {
+            src: nearest(src),
+            dst: nearest(dst),
+            lines: nearest(&lines),
+            line_count: line_count as i32,
+            t: t as f32,
+            p: p as f32,
+            a: a as f32,
+            b: b as f32,
+            viewport: [
+                viewport.left as f32,
+                viewport.bottom as f32,
+                viewport.width as f32,
+                viewport.height as f32,
+            ],
+            image_size: [width as f32, height as f32],
+            rotate_lines: self.line_mode == LineInterpolation::CentreAngleLength,
+            blend_space: self.blend_space as i32,
+        };
+        let params = DrawParameters {
+            viewport: Some(viewport),
+            ..Default::default()
+        };
+        target
+            .draw(
+                EmptyVertexAttributes { len: 4 },
+                NoIndices(PrimitiveType::TriangleStrip),
+                &self.program,
+                &uniforms,
+                &params,
+            )
+            .unwrap();
+    }
+
+    // Offscreen morph at full resolution, read back into an image
+    pub fn render<F: Facade>(
+        &self,
+        facade: &F,
+        src: &RgbaImage,
+        dst: &RgbaImage,
+        src_lines: &[Vec<Vertex>],
+        dst_lines: &[Vec<Vertex>],
+        params: (f64, f64, f64, f64),
+    ) -> RgbaImage {
+        let (width, height) = src.dimensions();
+        let src_tex = GpuMorph::texture(facade, src);
+        let dst_tex = GpuMorph::texture(facade, dst);
+        let output = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::U8U8U8U8,
+            MipmapsOption::NoMipmap,
+            width,
+            height,
+        )
+        .unwrap();
+        let viewport = Rect {
+            left: 0,
+            bottom: 0,
+            width,
+            height,
+        };
+        self.draw(
+            facade,
+            &mut output.as_surface(),
+            viewport,
+            &src_tex,
+            &dst_tex,
+            src_lines,
+            dst_lines,
+            params,
+        );
+
+        // Read back bottom row first
+        let raw: RawImage2d<u8> = output.read();
+        let img = RgbaImage::from_raw(width, height, raw.data.into_owned()).unwrap();
+        image::imageops::flip_vertical(&img)
+    }
+}
@@ -0,0 +1,54 @@
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use crate::{Morph, WarpField};
+
+// How a layer is read between pixels and combined at t
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sampling {
+    Nearest,  // Whole pixels from whichever image t is closer to, so label values stay exact
+    Bilinear, // Smoothly sampled and cross dissolved like the colours, for mattes and depth
+}
+
+// Extra maps that deform with the images, e.g. a matte, a depth map or segmentation labels.
+// src and dst are the same sizes as the morph's src and dst images
+#[derive(Copy, Clone, Debug)]
+pub struct Layer<'a> {
+    pub src: &'a RgbaImage,
+    pub dst: &'a RgbaImage,
+    pub sampling: Sampling,
+}
+
+impl<'a> Layer<'a> {
+    pub fn new(src: &'a RgbaImage, dst: &'a RgbaImage, sampling: Sampling) -> Self {
+        Layer { src, dst, sampling }
+    }
+
+    // The layer warped by the same fields as the colours and combined at the morph's t
+    pub fn render(
+        &self,
+        morph: &Morph,
+        (src_field, dst_field): &(WarpField, WarpField),
+    ) -> RgbaImage {
+        let t = morph.t.clamp(0.0, 1.0);
+        match self.sampling {
+            Sampling::Nearest => {
+                if t < 0.5 {
+                    src_field.apply(self.src)
+                } else {
+                    dst_field.apply(self.dst)
+                }
+            }
+            Sampling::Bilinear => {
+                let (src_warp, dst_warp) = (src_field.sample(self.src), dst_field.sample(self.dst));
+                ImageBuffer::from_fn(src_warp.width(), src_warp.height(), |x, y| {
+                    let (Rgba(a), Rgba(b)) = (src_warp.get_pixel(x, y), dst_warp.get_pixel(x, y));
+                    let mut mixed = [0; 4];
+                    for c in 0..4 {
+                        mixed[c] = ((1.0 - t) * a[c] as f64 + t * b[c] as f64).round() as u8;
+                    }
+                    Rgba(mixed)
+                })
+            }
+        }
+    }
+}
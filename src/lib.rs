@@ -2,20 +2,144 @@
 #[macro_use]
 extern crate cgmath;
 extern crate conv;
+#[macro_use]
 extern crate glium;
 extern crate image;
 extern crate imageproc;
 extern crate num_traits;
+extern crate png;
+
+pub mod align;
+pub mod color_space;
+pub mod color_transfer;
+pub mod correspondence;
+pub mod curves;
+pub mod editor;
+pub mod feature_lines;
+pub mod field;
+pub mod gpu;
+pub mod layers;
+pub mod multi;
+pub mod progress;
+pub mod snap;
+pub mod strokes;
+pub mod timeline;
+pub mod view;
 
+pub use color_space::BlendSpace;
+pub use color_transfer::ColorTransfer;
+pub use editor::{EditorEvent, EditorState};
+pub use feature_lines::{FeatureLines, LinePair, LineSet, Side};
+pub use field::WarpField;
 use image::{ImageBuffer, Pixel, RgbaImage};
+pub use layers::{Layer, Sampling};
+pub use multi::MultiMorph;
+pub use progress::{CancelToken, MorphError, Progress, Stage};
+use std::f64::consts::PI;
+pub use timeline::{Easing, Interpolation, LineTiming, Timeline};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vertex {
    pub position: [f64; 2],
 }
 
 glium::implement_vertex!(Vertex, position);
 
+// Shortest an extrapolated line may get, as a fraction of the line in the image it moves away from
+const MIN_EXTRAPOLATED_LENGTH: f64 = 0.25;
+
+// Extrapolated lines can shrink to nothing or flip round, which would tear the warp apart. Such a
+// line is rebuilt about its middle, pointing like the nearest image's line and at the shortest length
+fn extrapolated_line(line: Vec<Vertex>, nearest: &[Vertex]) -> Vec<Vertex> {
+    let (p, q) = (line[0].position, line[1].position);
+    let (np, nq) = (nearest[0].position, nearest[1].position);
+    let near_len = (nq[0] - np[0]).hypot(nq[1] - np[1]);
+    if near_len < f64::EPSILON {
+        return line;
+    }
+    let dir = [(nq[0] - np[0]) / near_len, (nq[1] - np[1]) / near_len];
+    let along = (q[0] - p[0]) * dir[0] + (q[1] - p[1]) * dir[1];
+    let min_len = MIN_EXTRAPOLATED_LENGTH * near_len;
+    if along >= min_len {
+        return line;
+    }
+    let mid = [(p[0] + q[0]) / 2.0, (p[1] + q[1]) / 2.0];
+    let half = [dir[0] * min_len / 2.0, dir[1] * min_len / 2.0];
+    vec![
+        Vertex {
+            position: [mid[0] - half[0], mid[1] - half[1]],
+        },
+        Vertex {
+            position: [mid[0] + half[0], mid[1] + half[1]],
+        },
+    ]
+}
+
+// The face pushed further from the reference by amount, where 0 leaves it as it is and 1 doubles
+// every difference in shape. The colours stay the face's; render or morph it as usual
+pub fn caricature<'a>(
+    face: &'a RgbaImage,
+    face_lines: &'a [Vec<Vertex>],
+    reference: &'a RgbaImage,
+    reference_lines: &'a [Vec<Vertex>],
+    amount: f64,
+    (p, a, b): (f64, f64, f64),
+) -> Morph<'a> {
+    Morph::new(
+        reference,
+        face,
+        reference_lines,
+        face_lines,
+        (1.0 + amount, p, a, b),
+    )
+}
+
+// How each line gets from its source to its destination position
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineInterpolation {
+    Endpoints, // Both ends in a straight line, which shortens lines that turn
+    // Middle in a straight line while turning the shorter way round and changing length evenly,
+    // as Beier and Neely suggest for lines that rotate a lot
+    CentreAngleLength,
+}
+
+fn rotated_line(src: &[Vertex], dst: &[Vertex], t: f64) -> Vec<Vertex> {
+    let centre_angle_length = |line: &[Vertex]| {
+        let ([x0, y0], [x1, y1]) = (line[0].position, line[1].position);
+        let centre = [(x0 + x1) / 2.0, (y0 + y1) / 2.0];
+        ((y1 - y0).atan2(x1 - x0), (x1 - x0).hypot(y1 - y0), centre)
+    };
+    let (src_angle, src_len, src_centre) = centre_angle_length(src);
+    let (dst_angle, dst_len, dst_centre) = centre_angle_length(dst);
+    let mut turn = (dst_angle - src_angle) % (2.0 * PI);
+    if turn > PI {
+        turn -= 2.0 * PI;
+    } else if turn < -PI {
+        turn += 2.0 * PI;
+    }
+    let angle = src_angle + t * turn;
+    let mut half = ((1.0 - t) * src_len + t * dst_len) / 2.0;
+    // Extrapolated lines keep turning but are kept from shrinking away, like extrapolated_line
+    if t < 0.0 {
+        half = half.max(MIN_EXTRAPOLATED_LENGTH * src_len / 2.0);
+    } else if t > 1.0 {
+        half = half.max(MIN_EXTRAPOLATED_LENGTH * dst_len / 2.0);
+    }
+    let centre = [
+        (1.0 - t) * src_centre[0] + t * dst_centre[0],
+        (1.0 - t) * src_centre[1] + t * dst_centre[1],
+    ];
+    let (dx, dy) = (angle.cos() * half, angle.sin() * half);
+    vec![
+        Vertex {
+            position: [centre[0] - dx, centre[1] - dy],
+        },
+        Vertex {
+            position: [centre[0] + dx, centre[1] + dy],
+        },
+    ]
+}
+
 pub struct Morph<'a> {
     pub src: &'a RgbaImage,
     pub dst: &'a RgbaImage,
@@ -25,6 +149,12 @@ pub struct Morph<'a> {
     pub p: f64,
     pub a: f64,
     pub b: f64,
+    pub inter_lines: Option<&'a [Vec<Vertex>]>, // Used instead of interpolating the lines at t
+    pub line_mode: LineInterpolation,
+    pub timings: Option<&'a [LineTiming]>, // One per straight line, each on its own schedule
+    pub blend_space: BlendSpace,
+    pub color_transfer: Option<ColorTransfer>, // Matches the warps' colours before the dissolve
+    pub transfer_masks: Option<(&'a RgbaImage, &'a RgbaImage)>, // Limits the colour transfer
 }
 
 impl<'a> Morph<'a> {
@@ -44,30 +174,108 @@ impl<'a> Morph<'a> {
             p,
             a,
             b,
+            inter_lines: None,
+            line_mode: LineInterpolation::Endpoints,
+            timings: None,
+            blend_space: BlendSpace::default(),
+            color_transfer: None,
+            transfer_masks: None,
+        }
+    }
+
+    // Samples and mixes the colours in space, linear light by default
+    pub fn with_blend_space(self, blend_space: BlendSpace) -> Self {
+        Morph {
+            blend_space,
+            ..self
+        }
+    }
+
+    // Moves both warps' colours toward their mix at t before blending, see color_transfer
+    pub fn with_color_transfer(self, method: ColorTransfer) -> Self {
+        Morph {
+            color_transfer: Some(method),
+            ..self
+        }
+    }
+
+    // Only measures and changes colours inside the masks, e.g. the faces, weighted by their red
+    // channel. Each mask is the size of its image and warped along with it
+    pub fn with_transfer_masks(self, src_mask: &'a RgbaImage, dst_mask: &'a RgbaImage) -> Self {
+        Morph {
+            transfer_masks: Some((src_mask, dst_mask)),
+            ..self
+        }
+    }
+
+    pub fn with_line_mode(self, line_mode: LineInterpolation) -> Self {
+        Morph { line_mode, ..self }
+    }
+
+    // Warps to the given lines, e.g. from a Timeline, while t still sets the colour blend
+    pub fn with_inter_lines(self, inter_lines: &'a [Vec<Vertex>]) -> Self {
+        Morph {
+            inter_lines: Some(inter_lines),
+            ..self
+        }
+    }
+
+    // Gives each line its own start, end and easing within the transition, see LineTiming.
+    // Timings match the straight lines, so features split by curves::split_lines need theirs
+    // split the same way by curves::split_timings
+    pub fn with_timings(self, timings: &'a [LineTiming]) -> Self {
+        Morph {
+            timings: Some(timings),
+            ..self
+        }
+    }
+
+    // How far line i is through its own transition at t, which is t itself without timings
+    pub fn line_t(&self, i: usize) -> f64 {
+        match self.timings.and_then(|timings| timings.get(i)) {
+            Some(timing) => timing.progress(self.t),
+            None => self.t,
         }
     }
 
     pub fn interpolate_lines(&self) -> Vec<Vec<Vertex>> {
+        if let Some(inter_lines) = self.inter_lines {
+            return inter_lines.to_vec();
+        }
         let mut inter_lines: Vec<Vec<Vertex>> = Vec::new();
         for i in 0..self.src_lines.len() {
-            let mut new_inter_line: Vec<Vertex> = Vec::new();
-            new_inter_line.push(Vertex {
-                position: [
-                    (1.0f64 - self.t) * self.src_lines[i][0].position[0]
-                        + self.t * self.dst_lines[i][0].position[0],
-                    (1.0f64 - self.t) * self.src_lines[i][0].position[1]
-                        + self.t * self.dst_lines[i][0].position[1],
+            let t = self.line_t(i);
+            let new_inter_line: Vec<Vertex> = match self.line_mode {
+                LineInterpolation::Endpoints => vec![
+                    Vertex {
+                        position: [
+                            (1.0f64 - t) * self.src_lines[i][0].position[0]
+                                + t * self.dst_lines[i][0].position[0],
+                            (1.0f64 - t) * self.src_lines[i][0].position[1]
+                                + t * self.dst_lines[i][0].position[1],
+                        ],
+                    },
+                    Vertex {
+                        position: [
+                            (1.0f64 - t) * self.src_lines[i][1].position[0]
+                                + t * self.dst_lines[i][1].position[0],
+                            (1.0f64 - t) * self.src_lines[i][1].position[1]
+                                + t * self.dst_lines[i][1].position[1],
+                        ],
+                    },
                 ],
-            });
-            new_inter_line.push(Vertex {
-                position: [
-                    (1.0f64 - self.t) * self.src_lines[i][1].position[0]
-                        + self.t * self.dst_lines[i][1].position[0],
-                    (1.0f64 - self.t) * self.src_lines[i][1].position[1]
-                        + self.t * self.dst_lines[i][1].position[1],
-                ],
-            });
-            inter_lines.push(new_inter_line);
+                LineInterpolation::CentreAngleLength => {
+                    rotated_line(&self.src_lines[i], &self.dst_lines[i], t)
+                }
+            };
+            let guarded = self.line_mode == LineInterpolation::Endpoints;
+            if guarded && t < 0.0 {
+                inter_lines.push(extrapolated_line(new_inter_line, &self.src_lines[i]));
+            } else if guarded && t > 1.0 {
+                inter_lines.push(extrapolated_line(new_inter_line, &self.dst_lines[i]));
+            } else {
+                inter_lines.push(new_inter_line);
+            }
         }
         inter_lines
     }
@@ -78,6 +286,16 @@ impl<'a> Morph<'a> {
         y: f64,
         lines: &[Vec<Vertex>],
         img_lines: Vec<Vec<Vertex>>,
+    ) -> (f64, f64) {
+        self.warp_point(x, y, lines, &img_lines)
+    }
+
+    pub(crate) fn warp_point(
+        &self,
+        x: f64,
+        y: f64,
+        lines: &[Vec<Vertex>],
+        img_lines: &[Vec<Vertex>],
     ) -> (f64, f64) {
         let mut pd: Vec<f64> = Vec::new(); // X - P vector
         let mut pq: Vec<f64> = Vec::new(); // P - Q vector
@@ -94,6 +312,9 @@ impl<'a> Morph<'a> {
             pq.push(lines[i][1].position[0] - lines[i][0].position[0]);
             pq.push(lines[i][1].position[1] - lines[i][0].position[1]);
             let inter_len = pq[0] * pq[0] + pq[1] * pq[1];
+            if inter_len < f64::EPSILON {
+                continue; // Zero length lines have no direction to warp along
+            }
             let u = (pd[0] * pq[0] + pd[1] * pq[1]) / inter_len;
             let inter_len = inter_len.sqrt();
             let v = (pd[0] * pq[1] - pd[1] * pq[0]) / inter_len;
@@ -101,6 +322,9 @@ impl<'a> Morph<'a> {
             pq[1] = img_lines[i][1].position[1] - img_lines[i][0].position[1];
 
             let src_len = (pq[0] * pq[0] + pq[1] * pq[1]).sqrt();
+            if src_len < f64::EPSILON {
+                continue;
+            }
             let xx = img_lines[i][0].position[0] + u * pq[0] + v * pq[1] / src_len;
             let yy = img_lines[i][0].position[1] + u * pq[1] - v * pq[0] / src_len;
             let dx = x - xx;
@@ -120,14 +344,18 @@ impl<'a> Morph<'a> {
             dy_sum += dy * weight;
             weight_sum += weight;
         }
-        (x + dx_sum / weight_sum, y - dy_sum / weight_sum)
+        if weight_sum == 0.0 {
+            return (x, y);
+        }
+        (x - dx_sum / weight_sum, y - dy_sum / weight_sum)
     }
 
-    pub fn bilinear_interpolate(&self, img: &RgbaImage, x: f64, y: f64) -> (f64, f64, f64) {
+    // The four pixels around (x, y) with their weights
+    fn bilinear_taps(img: &RgbaImage, x: f64, y: f64) -> [((u32, u32), f64); 4] {
         let (width, height) = img.dimensions();
         let i: f64 = if x == 0.0 {
             1.0
-        } else if (x.ceil() - width as f64).abs() < 0.1 as f64 {
+        } else if (x.ceil() - width as f64).abs() < 0.1f64 {
             (width - 1) as f64
         } else {
             x.ceil()
@@ -135,7 +363,7 @@ impl<'a> Morph<'a> {
 
         let j: f64 = if y == 0.0 {
             1.0
-        } else if (y.ceil() - height as f64).abs() < 0.1 as f64 {
+        } else if (y.ceil() - height as f64).abs() < 0.1f64 {
             (height - 1) as f64
         } else {
             y.ceil()
@@ -143,24 +371,31 @@ impl<'a> Morph<'a> {
 
         let alpha = i - x;
         let beta = j - y;
-        let pix00 = img.get_pixel(i as u32 - 1, j as u32 - 1).to_rgba();
-        let pix01 = img.get_pixel(i as u32, j as u32 - 1).to_rgba();
-        let pix10 = img.get_pixel(i as u32 - 1, j as u32).to_rgba();
-        let pix11 = img.get_pixel(i as u32, j as u32).to_rgba();
-
-        let rgb0 = alpha * beta * pix00.0[0] as f64
-            + (1.0f64 - alpha) * beta * pix01.0[0] as f64
-            + alpha * (1.0f64 - beta) * pix10.0[0] as f64
-            + (1.0f64 - alpha) * (1.0f64 - beta) * pix11.0[0] as f64;
-        let rgb1 = alpha * beta * pix00.0[1] as f64
-            + (1.0f64 - alpha) * beta * pix01.0[1] as f64
-            + alpha * (1.0f64 - beta) * pix10.0[1] as f64
-            + (1.0f64 - alpha) * (1.0f64 - beta) * pix11.0[1] as f64;
-        let rgb2 = alpha * beta * pix00.0[2] as f64
-            + (1.0f64 - alpha) * beta * pix01.0[2] as f64
-            + alpha * (1.0f64 - beta) * pix10.0[2] as f64
-            + (1.0f64 - alpha) * (1.0f64 - beta) * pix11.0[2] as f64;
-        (rgb0, rgb1, rgb2)
+        let (i, j) = (i as u32, j as u32);
+        [
+            ((i - 1, j - 1), alpha * beta),
+            ((i, j - 1), (1.0f64 - alpha) * beta),
+            ((i - 1, j), alpha * (1.0f64 - beta)),
+            ((i, j), (1.0f64 - alpha) * (1.0f64 - beta)),
+        ]
+    }
+
+    pub fn bilinear_interpolate(&self, img: &RgbaImage, x: f64, y: f64) -> (f64, f64, f64) {
+        let [r, g, b] = Morph::sample(img, x, y, BlendSpace::Srgb);
+        (r, g, b)
+    }
+
+    // Bilinear sample decoded into space, so linear spaces weight the pixels in linear light
+    fn sample(img: &RgbaImage, x: f64, y: f64, space: BlendSpace) -> [f64; 3] {
+        let mut rgb = [0.0; 3];
+        for &((px, py), weight) in Morph::bilinear_taps(img, x, y).iter() {
+            let pix = img.get_pixel(px, py).to_rgba();
+            let decoded = space.decode([pix.0[0] as f64, pix.0[1] as f64, pix.0[2] as f64]);
+            for c in 0..3 {
+                rgb[c] += weight * decoded[c];
+            }
+        }
+        rgb
     }
 
     pub fn interpolate_color(
@@ -170,96 +405,281 @@ impl<'a> Morph<'a> {
         src: &RgbaImage,
         dst: &RgbaImage,
     ) -> (f64, f64, f64) {
-        let (src_r, src_g, src_b) = self.bilinear_interpolate(src, src_pt[0], src_pt[1]);
-        let (dst_r, dst_g, dst_b) = self.bilinear_interpolate(dst, dst_pt[0], dst_pt[1]);
-        let rgb0 = src_r * (1.0f64 - self.t) + dst_r * self.t;
-        let rgb1 = src_g * (1.0f64 - self.t) + dst_g * self.t;
-        let rgb2 = src_b * (1.0f64 - self.t) + dst_b * self.t;
+        let src_color = Morph::sample(src, src_pt[0], src_pt[1], self.blend_space);
+        let dst_color = Morph::sample(dst, dst_pt[0], dst_pt[1], self.blend_space);
+        // Past either image the shape keeps changing but the colours stay that image's
+        let t = self.t.clamp(0.0, 1.0);
+        let [rgb0, rgb1, rgb2] = self
+            .blend_space
+            .mix(&[(src_color, 1.0f64 - t), (dst_color, t)]);
         (rgb0, rgb1, rgb2)
     }
 
-    pub fn morph(&self) -> RgbaImage {
-        let (src_w, src_h) = self.src.dimensions();
-        let (dst_w, dst_h) = self.dst.dimensions();
-        let mut morphed_img: RgbaImage = ImageBuffer::new(dst_w, dst_h);
-        let mut src_map: RgbaImage = ImageBuffer::new(src_w, src_h);
-        let mut dst_map: RgbaImage = ImageBuffer::new(dst_w, dst_h);
+    // Resamples img so its feature lines land on the intermediate lines
+    pub fn warp_image(&self, img: &RgbaImage, img_lines: &[Vec<Vertex>]) -> RgbaImage {
+        self.warp_stage(
+            img,
+            None,
+            img_lines,
+            Stage::SrcWarp,
+            &mut |_| (),
+            &CancelToken::new(),
+        )
+        .unwrap()
+        .0
+    }
+
+    // Warps img, and mask along with it when there is one, in the same pass
+    fn warp_stage(
+        &self,
+        img: &RgbaImage,
+        mask: Option<&RgbaImage>,
+        img_lines: &[Vec<Vertex>],
+        stage: Stage,
+        observer: &mut dyn FnMut(Progress),
+        cancel: &CancelToken,
+    ) -> Result<(RgbaImage, Option<RgbaImage>), MorphError> {
+        let (width, height) = img.dimensions();
+        let mut warped: RgbaImage = ImageBuffer::new(width, height);
+        let mut warped_mask = mask.map(|_| ImageBuffer::new(width, height));
         let inter_lines = self.interpolate_lines();
 
-        for y in 0..src_h - 1 {
-            for x in 0..src_w - 1 {
-                let (src_x, src_y) =
-                    self.warp(x as f64, y as f64, &inter_lines, self.src_lines.to_vec());
-                let src_x = if src_x < 0.0 {
-                    0.0
-                } else if src_x > (src_w - 1).into() {
-                    (src_w - 1) as f64
-                } else {
-                    src_x
-                };
-                let src_y = if src_y < 0.0 {
-                    0.0
-                } else if src_y > (src_h - 1).into() {
-                    (src_h - 1) as f64
-                } else {
-                    src_y
-                };
-                if src_x > 0.0
-                    && src_y > 0.0
-                    && (src_x as u32) < src_w - 1
-                    && (src_y as u32) < src_h - 1
+        for y in 0..height - 1 {
+            cancel.check()?;
+            for x in 0..width - 1 {
+                let (img_x, img_y) = self.warp_point(x as f64, y as f64, &inter_lines, img_lines);
+                let img_x = img_x.clamp(0.0, (width - 1) as f64);
+                let img_y = img_y.clamp(0.0, (height - 1) as f64);
+                if img_x > 0.0
+                    && img_y > 0.0
+                    && (img_x as u32) < width - 1
+                    && (img_y as u32) < height - 1
                 {
-                    src_map.put_pixel(x, y, *self.src.get_pixel(src_x as u32, src_y as u32));
+                    let (img_x, img_y) = (img_x as u32, img_y as u32);
+                    warped.put_pixel(x, y, *img.get_pixel(img_x, img_y));
+                    if let (Some(mask), Some(warped_mask)) = (mask, warped_mask.as_mut()) {
+                        if img_x < mask.width() && img_y < mask.height() {
+                            warped_mask.put_pixel(x, y, *mask.get_pixel(img_x, img_y));
+                        }
+                    }
                 }
             }
+            observer(Progress::Row {
+                stage,
+                row: y,
+                rows: height - 1,
+            });
         }
+        observer(Progress::Stage(stage));
+        Ok((warped, warped_mask))
+    }
 
-        for y in 0..dst_h - 1 {
-            for x in 0..dst_w - 1 {
-                let (dst_x, dst_y) =
-                    self.warp(x as f64, y as f64, &inter_lines, self.dst_lines.to_vec());
-                let dst_x = if dst_x < 0.0 {
-                    0.0
-                } else if dst_x > (dst_w - 1).into() {
-                    (dst_w - 1) as f64
-                } else {
-                    dst_x
-                };
-                let dst_y = if dst_y < 0.0 {
-                    0.0
-                } else if dst_y > (dst_h - 1).into() {
-                    (dst_h - 1) as f64
-                } else {
-                    dst_y
-                };
-                if dst_x > 0.0
-                    && dst_y > 0.0
-                    && (dst_x as u32) < dst_w - 1
-                    && (dst_y as u32) < dst_h - 1
-                {
-                    dst_map.put_pixel(x, y, *self.dst.get_pixel(dst_x as u32, dst_y as u32));
-                }
-            }
+    // The two warped images after the colour transfer, unchanged without one. masks are the
+    // transfer masks already warped the same way as the images
+    pub fn transfer_colors(
+        &self,
+        src_warp: &RgbaImage,
+        dst_warp: &RgbaImage,
+        masks: Option<(&RgbaImage, &RgbaImage)>,
+    ) -> (RgbaImage, RgbaImage) {
+        match self.color_transfer {
+            Some(method) => color_transfer::transfer(
+                method,
+                src_warp,
+                dst_warp,
+                masks,
+                self.t.clamp(0.0, 1.0),
+                self.blend_space,
+            ),
+            None => (src_warp.clone(), dst_warp.clone()),
+        }
+    }
+
+    // The transfer masks, when there is a colour transfer for them to limit
+    fn active_masks(&self) -> Option<(&'a RgbaImage, &'a RgbaImage)> {
+        self.color_transfer.and(self.transfer_masks)
+    }
+
+    // transfer_colors on the warps, given the active masks warped along with them
+    fn transferred(
+        &self,
+        warps: (RgbaImage, RgbaImage),
+        masks: Option<(RgbaImage, RgbaImage)>,
+    ) -> (RgbaImage, RgbaImage) {
+        if self.color_transfer.is_none() {
+            return warps;
         }
+        self.transfer_colors(
+            &warps.0,
+            &warps.1,
+            masks
+                .as_ref()
+                .map(|(src_mask, dst_mask)| (src_mask, dst_mask)),
+        )
+    }
 
-        let mut src_warp = image::DynamicImage::ImageRgba8(src_map).flipv();
-        src_warp.flipv().save("src_warp.png").unwrap();
-        let mut dst_warp = image::DynamicImage::ImageRgba8(dst_map).flipv();
-        dst_warp.flipv().save("dst_warp.png").unwrap();
+    // Cross dissolve of the two warped images at t
+    pub fn blend(&self, src_warp: &RgbaImage, dst_warp: &RgbaImage) -> RgbaImage {
+        self.blend_stage(src_warp, dst_warp, &mut |_| (), &CancelToken::new())
+            .unwrap()
+    }
 
-        for y in 0..dst_h - 1 {
-            for x in 0..dst_w - 1 {
+    fn blend_stage(
+        &self,
+        src_warp: &RgbaImage,
+        dst_warp: &RgbaImage,
+        observer: &mut dyn FnMut(Progress),
+        cancel: &CancelToken,
+    ) -> Result<RgbaImage, MorphError> {
+        let (width, height) = dst_warp.dimensions();
+        let mut morphed_img: RgbaImage = ImageBuffer::new(width, height);
+        for y in 0..height - 1 {
+            cancel.check()?;
+            for x in 0..width - 1 {
                 let (r, g, b) = self.interpolate_color(
                     vec![x as f64, y as f64],
                     vec![x as f64, y as f64],
-                    &src_warp.as_rgba8().unwrap(),
-                    &dst_warp.as_rgba8().unwrap(),
+                    src_warp,
+                    dst_warp,
                 );
                 let color = Pixel::from_channels(r as u8, g as u8, b as u8, 255);
                 morphed_img.put_pixel(x, y, color);
             }
+            observer(Progress::Row {
+                stage: Stage::Blend,
+                row: y,
+                rows: height - 1,
+            });
         }
+        observer(Progress::Stage(Stage::Blend));
+        Ok(morphed_img)
+    }
+
+    // Morph without writing the intermediate warps to disk
+    pub fn render(&self) -> RgbaImage {
+        self.render_with(&mut |_| (), &CancelToken::new()).unwrap()
+    }
+
+    // Render reporting each row and stage to observer, stopping with an error soon after cancel
+    pub fn render_with(
+        &self,
+        observer: &mut dyn FnMut(Progress),
+        cancel: &CancelToken,
+    ) -> Result<RgbaImage, MorphError> {
+        let (src_warp, dst_warp) = self.warp_both(observer, cancel)?;
+        self.blend_stage(&src_warp, &dst_warp, observer, cancel)
+    }
+
+    // Where both warps take each pixel from, to render again later without recomputing them
+    pub fn warp_fields(&self) -> (WarpField, WarpField) {
+        (
+            WarpField::from_morph(self, Side::Src),
+            WarpField::from_morph(self, Side::Dst),
+        )
+    }
+
+    // Render of any two images the size of the fields, e.g. retouched versions of src and dst,
+    // sampling between pixels and blending at t
+    pub fn render_fields(
+        &self,
+        (src_field, dst_field): &(WarpField, WarpField),
+        src: &RgbaImage,
+        dst: &RgbaImage,
+    ) -> RgbaImage {
+        let warps = (
+            src_field.sample_in(src, self.blend_space),
+            dst_field.sample_in(dst, self.blend_space),
+        );
+        let masks = self
+            .active_masks()
+            .map(|(src_mask, dst_mask)| (src_field.apply(src_mask), dst_field.apply(dst_mask)));
+        let (src_warp, dst_warp) = self.transferred(warps, masks);
+        self.blend(&src_warp, &dst_warp)
+    }
+
+    // Render along with every layer deformed the same way, returned in the same order
+    pub fn render_layers(&self, layers: &[Layer]) -> (RgbaImage, Vec<RgbaImage>) {
+        let fields = self.warp_fields();
+        let warps = (fields.0.apply(self.src), fields.1.apply(self.dst));
+        let masks = self
+            .active_masks()
+            .map(|(src_mask, dst_mask)| (fields.0.apply(src_mask), fields.1.apply(dst_mask)));
+        let (src_warp, dst_warp) = self.transferred(warps, masks);
+        let layers = layers
+            .iter()
+            .map(|layer| layer.render(self, &fields))
+            .collect();
+        (self.blend(&src_warp, &dst_warp), layers)
+    }
 
-        morphed_img
+    // Render that also saves both warps as src_warp.png and dst_warp.png
+    pub fn morph(&self) -> RgbaImage {
+        let (src_warp, dst_warp) = self.warp_both(&mut |_| (), &CancelToken::new()).unwrap();
+        src_warp.save("src_warp.png").unwrap();
+        dst_warp.save("dst_warp.png").unwrap();
+        self.blend(&src_warp, &dst_warp)
+    }
+
+    // Morph reporting progress like render_with. The warps aren't saved, so a cancelled or
+    // failed morph leaves nothing on disk
+    pub fn morph_with(
+        &self,
+        observer: &mut dyn FnMut(Progress),
+        cancel: &CancelToken,
+    ) -> Result<RgbaImage, MorphError> {
+        self.render_with(observer, cancel)
+    }
+
+    fn warp_both(
+        &self,
+        observer: &mut dyn FnMut(Progress),
+        cancel: &CancelToken,
+    ) -> Result<(RgbaImage, RgbaImage), MorphError> {
+        // The masks are warped in the same rows as their images, so they report progress and
+        // stop on cancel with them
+        let masks = self.active_masks();
+        let (src_warp, src_mask) = self.warp_stage(
+            self.src,
+            masks.map(|(src_mask, _)| src_mask),
+            self.src_lines,
+            Stage::SrcWarp,
+            observer,
+            cancel,
+        )?;
+        let (dst_warp, dst_mask) = self.warp_stage(
+            self.dst,
+            masks.map(|(_, dst_mask)| dst_mask),
+            self.dst_lines,
+            Stage::DstWarp,
+            observer,
+            cancel,
+        )?;
+        Ok(self.transferred((src_warp, dst_warp), src_mask.zip(dst_mask)))
+    }
+
+    // Frames evenly spaced from the source (t = 0) to the destination (t = 1), ignoring t and
+    // inter_lines, reporting each frame's rows and stages and then the frame itself
+    pub fn sequence(
+        &self,
+        frames: usize,
+        observer: &mut dyn FnMut(Progress),
+        cancel: &CancelToken,
+    ) -> Result<Vec<RgbaImage>, MorphError> {
+        let mut images = Vec::with_capacity(frames);
+        for frame in 0..frames {
+            let t = if frames > 1 {
+                frame as f64 / (frames - 1) as f64
+            } else {
+                self.t
+            };
+            let morph = Morph {
+                t,
+                inter_lines: None,
+                ..*self
+            };
+            images.push(morph.render_with(observer, cancel)?);
+            observer(Progress::Frame { frame, frames });
+        }
+        Ok(images)
     }
 }
@@ -0,0 +1,156 @@
+use image::{ImageBuffer, Pixel, RgbaImage};
+
+use crate::curves::{segment_distance, segment_fraction};
+use crate::{BlendSpace, LineSet, Morph, Vertex};
+
+// Any number of same sized images morphed together. Their lines are averaged by weight, every
+// image is warped to that average and the warps are blended with the same weights. Lines are
+// straight, so curve features need cutting into two point lines first
+pub struct MultiMorph<'a> {
+    pub images: &'a [RgbaImage],
+    pub lines: &'a [LineSet], // One set per image, matching by index
+    pub weights: Vec<f64>,    // Scaled to sum to 1
+    pub p: f64,
+    pub a: f64,
+    pub b: f64,
+    pub blend_space: BlendSpace,
+}
+
+impl<'a> MultiMorph<'a> {
+    pub fn new(
+        images: &'a [RgbaImage],
+        lines: &'a [LineSet],
+        weights: &[f64],
+        (p, a, b): (f64, f64, f64),
+    ) -> Self {
+        assert_eq!(images.len(), lines.len(), "every image needs its lines");
+        assert_eq!(images.len(), weights.len(), "every image needs a weight");
+        for img in images {
+            assert_eq!(
+                img.dimensions(),
+                images[0].dimensions(),
+                "images must be the same size"
+            );
+        }
+        for line in lines.iter().flatten() {
+            assert_eq!(line.len(), 2, "every line needs exactly two points");
+        }
+        let total: f64 = weights.iter().sum();
+        assert!(total.abs() > f64::EPSILON, "weights must not sum to zero");
+        MultiMorph {
+            images,
+            lines,
+            weights: weights.iter().map(|w| w / total).collect(),
+            p,
+            a,
+            b,
+            blend_space: BlendSpace::default(),
+        }
+    }
+
+    pub fn with_blend_space(self, blend_space: BlendSpace) -> Self {
+        MultiMorph {
+            blend_space,
+            ..self
+        }
+    }
+
+    // Every image weighted equally, e.g. for an average face
+    pub fn average(images: &'a [RgbaImage], lines: &'a [LineSet], params: (f64, f64, f64)) -> Self {
+        MultiMorph::new(images, lines, &vec![1.0; images.len()], params)
+    }
+
+    // Weighted mean of each line's endpoints over all the images
+    pub fn average_lines(&self) -> LineSet {
+        (0..self.lines[0].len())
+            .map(|i| {
+                (0..2)
+                    .map(|end| {
+                        let mut position = [0.0, 0.0];
+                        for (lines, weight) in self.lines.iter().zip(self.weights.iter()) {
+                            position[0] += weight * lines[i][end].position[0];
+                            position[1] += weight * lines[i][end].position[1];
+                        }
+                        Vertex { position }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // One image resampled so its lines land on the average lines
+    pub fn warp_image(&self, index: usize) -> RgbaImage {
+        let average = self.average_lines();
+        let (img, lines) = (&self.images[index], &self.lines[index]);
+        // At t = 1 the intermediate lines are exactly the destination ones
+        let morph = Morph::new(img, img, lines, &average, (1.0, self.p, self.a, self.b));
+        morph.warp_image(img, lines)
+    }
+
+    // Weighted sum of the warped images' colours in blend_space
+    pub fn blend(&self, warps: &[RgbaImage]) -> RgbaImage {
+        let (width, height) = warps[0].dimensions();
+        for warp in warps {
+            assert_eq!(
+                warp.dimensions(),
+                (width, height),
+                "warps must be the same size"
+            );
+        }
+        let mut blended: RgbaImage = ImageBuffer::new(width, height);
+        for y in 0..height.saturating_sub(1) {
+            for x in 0..width.saturating_sub(1) {
+                let colors: Vec<([f64; 3], f64)> = warps
+                    .iter()
+                    .zip(self.weights.iter())
+                    .map(|(warp, &weight)| {
+                        (
+                            Morph::sample(warp, x as f64, y as f64, self.blend_space),
+                            weight,
+                        )
+                    })
+                    .collect();
+                let [r, g, b] = self.blend_space.mix(&colors);
+                let color = Pixel::from_channels(r as u8, g as u8, b as u8, 255);
+                blended.put_pixel(x, y, color);
+            }
+        }
+        blended
+    }
+
+    pub fn render(&self) -> RgbaImage {
+        let warps: Vec<RgbaImage> = (0..self.images.len()).map(|i| self.warp_image(i)).collect();
+        self.blend(&warps)
+    }
+}
+
+// Weights of a triangle's corners for a point, as used to blend three images from a triangle
+// control. Points outside are moved onto the nearest edge so no weight goes negative
+pub fn barycentric(corners: [[f64; 2]; 3], [x, y]: [f64; 2]) -> [f64; 3] {
+    let [[x0, y0], [x1, y1], [x2, y2]] = corners;
+    let area = (y1 - y2) * (x0 - x2) + (x2 - x1) * (y0 - y2);
+    if area.abs() < f64::EPSILON {
+        return [1.0 / 3.0; 3];
+    }
+    let w0 = ((y1 - y2) * (x - x2) + (x2 - x1) * (y - y2)) / area;
+    let w1 = ((y2 - y0) * (x - x2) + (x0 - x2) * (y - y2)) / area;
+    let weights = [w0, w1, 1.0 - w0 - w1];
+    if weights.iter().all(|&w| w >= 0.0) {
+        return weights;
+    }
+
+    // Nearest point on any edge, then split between that edge's two corners
+    let mut best = (f64::INFINITY, [0.0; 3]);
+    for &(i, j) in &[(0, 1), (1, 2), (2, 0)] {
+        let (p, q) = (corners[i], corners[j]);
+        let u = segment_fraction(p, q, [x, y]);
+        let dist = segment_distance(p, q, [x, y]);
+        if dist < best.0 {
+            let mut weights = [0.0; 3];
+            weights[i] = 1.0 - u;
+            weights[j] = u;
+            best = (dist, weights);
+        }
+    }
+    best.1
+}
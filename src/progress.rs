@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Steps of a single morph, in the order they run
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Stage {
+    SrcWarp,
+    DstWarp,
+    Blend,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 3] = [Stage::SrcWarp, Stage::DstWarp, Stage::Blend];
+
+    fn index(self) -> usize {
+        match self {
+            Stage::SrcWarp => 0,
+            Stage::DstWarp => 1,
+            Stage::Blend => 2,
+        }
+    }
+}
+
+// Reported to the observer after each row of a stage, each stage and each frame of a sequence
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Progress {
+    Row { stage: Stage, row: u32, rows: u32 },
+    Stage(Stage),
+    Frame { frame: usize, frames: usize },
+}
+
+impl Progress {
+    // Share of the work done from 0 to 1, of the morph for rows and stages and of the sequence for frames
+    pub fn fraction(&self) -> f64 {
+        let stages = Stage::ALL.len() as f64;
+        match *self {
+            Progress::Row { stage, row, rows } => {
+                (stage.index() as f64 + (row + 1) as f64 / rows as f64) / stages
+            }
+            Progress::Stage(stage) => (stage.index() + 1) as f64 / stages,
+            Progress::Frame { frame, frames } => (frame + 1) as f64 / frames as f64,
+        }
+    }
+}
+
+// Shared flag that asks a running morph to stop, checked between rows
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Err once cancelled, so loops can bail out with ?
+    pub fn check(&self) -> Result<(), MorphError> {
+        if self.is_cancelled() {
+            Err(MorphError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MorphError {
+    Cancelled,
+}
+
+impl fmt::Display for MorphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MorphError::Cancelled => write!(f, "morph cancelled"),
+        }
+    }
+}
+
+impl Error for MorphError {}
@@ -0,0 +1,88 @@
+use image::{GrayImage, RgbaImage};
+use imageproc::corners::corners_fast9;
+use imageproc::edges::canny;
+use imageproc::gradients::sobel_gradients;
+use imageproc::suppress::local_maxima;
+
+const CANNY_LOW: f32 = 20.0;
+const CANNY_HIGH: f32 = 50.0;
+const FAST_THRESHOLD: u8 = 20;
+const CORNER_BONUS: f32 = 1.0; // Corners always win over plain edges inside the radius
+
+// Per pixel snapping strength computed once from the original image
+pub struct EdgeSnapper {
+    strength: Vec<f32>,
+    width: u32,
+    height: u32,
+}
+
+impl EdgeSnapper {
+    pub fn new(img: &RgbaImage) -> Self {
+        let gray: GrayImage = image::imageops::grayscale(img);
+        let (width, height) = gray.dimensions();
+        if width == 0 || height == 0 {
+            // Nothing to snap to, and canny can't take an empty image
+            return EdgeSnapper {
+                strength: Vec::new(),
+                width,
+                height,
+            };
+        }
+        let edges = canny(&gray, CANNY_LOW, CANNY_HIGH);
+        let gradients = sobel_gradients(&gray);
+        let max_gradient = gradients.pixels().map(|p| p.0[0]).max().unwrap_or(0).max(1) as f32;
+
+        // Edge pixels are scored by gradient magnitude, everything else can't be snapped to
+        let mut strength: Vec<f32> = edges
+            .pixels()
+            .zip(gradients.pixels())
+            .map(|(edge, gradient)| {
+                if edge.0[0] > 0 {
+                    gradient.0[0] as f32 / max_gradient
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        let corners = local_maxima(&corners_fast9(&gray, FAST_THRESHOLD), 3);
+        let max_score = corners.iter().map(|c| c.score).fold(1.0f32, f32::max);
+        for corner in corners {
+            let idx = (corner.y * width + corner.x) as usize;
+            strength[idx] = CORNER_BONUS + corner.score / max_score;
+        }
+
+        EdgeSnapper {
+            strength,
+            width,
+            height,
+        }
+    }
+
+    // Strongest edge or corner within radius of (x, y), preferring the closer of equal ones
+    pub fn snap(&self, x: f64, y: f64, radius: f64) -> Option<(f64, f64)> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let x0 = (x - radius).floor().max(0.0) as u32;
+        let y0 = (y - radius).floor().max(0.0) as u32;
+        let x1 = ((x + radius).ceil().max(0.0) as u32).min(self.width.saturating_sub(1));
+        let y1 = ((y + radius).ceil().max(0.0) as u32).min(self.height.saturating_sub(1));
+
+        let mut best: Option<(f64, f64, f64)> = None;
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let strength = self.strength[(py * self.width + px) as usize];
+                let dist = (px as f64 - x).hypot(py as f64 - y);
+                if strength <= 0.0 || dist > radius {
+                    continue;
+                }
+                let score = strength as f64 - 0.01 * dist / radius;
+                if best.map_or(true, |(_, _, s)| score > s) {
+                    best = Some((px as f64, py as f64, score));
+                }
+            }
+        }
+        best.map(|(px, py, _)| (px, py))
+    }
+}
@@ -0,0 +1,217 @@
+use glium::backend::Facade;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{Blend, DrawParameters, Program, ProgramCreationError, Rect, Surface, VertexBuffer};
+
+const VERTEX_SHADER: &str = r#"
+    #version 140
+
+    in vec2 position;
+    in vec4 color;
+    in vec2 local;
+    in float radius;
+    in float half_length;
+    uniform mat4 projection;
+    out vec4 v_color;
+    out vec2 v_local;
+    out float v_radius;
+    out float v_half_length;
+
+    void main() {
+      v_color = color;
+      v_local = local;
+      v_radius = radius;
+      v_half_length = half_length;
+      gl_Position = projection * vec4(position, 0.0, 1.0);
+    }
+"#;
+
+// Fades out over the last pixel of the shape, so edges and segment ends are antialiased at any
+// thickness. Segments are boxes around their centre line and dots are circles
+const FRAGMENT_SHADER: &str = r#"
+    #version 140
+
+    in vec4 v_color;
+    in vec2 v_local;
+    in float v_radius;
+    in float v_half_length;
+    out vec4 color;
+
+    void main() {
+      float coverage;
+      if (v_half_length > 0.0) {
+        coverage = clamp(v_radius - abs(v_local.y) + 0.5, 0.0, 1.0)
+          * clamp(v_half_length - abs(v_local.x) + 0.5, 0.0, 1.0);
+      } else {
+        coverage = clamp(v_radius - length(v_local) + 0.5, 0.0, 1.0);
+      }
+      color = vec4(v_color.rgb, v_color.a * coverage);
+    }
+"#;
+
+const FEATHER: f64 = 1.0; // Room left around every shape for the antialiased edge
+
+// Local is the offset from the centre of the shape, along and across the line for strokes. Half
+// length is how far the line runs either side of the centre, 0 for dots
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StrokeVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+    pub local: [f32; 2],
+    pub radius: f32,
+    pub half_length: f32,
+}
+
+implement_vertex!(StrokeVertex, position, color, local, radius, half_length);
+
+// Thick lines and dots in window coordinates, built as triangles and drawn in one call
+#[derive(Clone, Debug, Default)]
+pub struct Strokes {
+    vertices: Vec<StrokeVertex>,
+}
+
+impl Strokes {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn vertices(&self) -> &[StrokeVertex] {
+        &self.vertices
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    fn quad(
+        &mut self,
+        corners: [([f64; 2], [f64; 2]); 4],
+        radius: f64,
+        half_length: f64,
+        color: [f32; 4],
+    ) {
+        for &i in &[0, 1, 2, 1, 3, 2] {
+            let (position, local) = corners[i];
+            self.vertices.push(StrokeVertex {
+                position: [position[0] as f32, position[1] as f32],
+                color,
+                local: [local[0] as f32, local[1] as f32],
+                radius: radius as f32,
+                half_length: half_length as f32,
+            });
+        }
+    }
+
+    pub fn segment(&mut self, a: [f64; 2], b: [f64; 2], width: f64, color: [f32; 4]) {
+        let length = (b[0] - a[0]).hypot(b[1] - a[1]);
+        if length < f64::EPSILON {
+            return;
+        }
+        let dir = [(b[0] - a[0]) / length, (b[1] - a[1]) / length];
+        let half = width / 2.0 + FEATHER;
+        let normal = [-dir[1] * half, dir[0] * half];
+        let start = [a[0] - dir[0] * FEATHER, a[1] - dir[1] * FEATHER];
+        let end = [b[0] + dir[0] * FEATHER, b[1] + dir[1] * FEATHER];
+        let along = length / 2.0 + FEATHER;
+        self.quad(
+            [
+                ([start[0] + normal[0], start[1] + normal[1]], [-along, half]),
+                ([end[0] + normal[0], end[1] + normal[1]], [along, half]),
+                (
+                    [start[0] - normal[0], start[1] - normal[1]],
+                    [-along, -half],
+                ),
+                ([end[0] - normal[0], end[1] - normal[1]], [along, -half]),
+            ],
+            width / 2.0,
+            length / 2.0,
+            color,
+        );
+    }
+
+    // Segments joined by dots so thick corners have no gaps
+    pub fn polyline(&mut self, points: &[[f64; 2]], width: f64, color: [f32; 4]) {
+        for pair in points.windows(2) {
+            self.segment(pair[0], pair[1], width, color);
+        }
+        if points.len() > 2 && width > 2.0 {
+            for &point in &points[1..points.len() - 1] {
+                self.dot(point, width / 2.0, color);
+            }
+        }
+    }
+
+    pub fn dot(&mut self, [x, y]: [f64; 2], radius: f64, color: [f32; 4]) {
+        let r = radius + FEATHER;
+        self.quad(
+            [
+                ([x - r, y - r], [-r, -r]),
+                ([x + r, y - r], [r, -r]),
+                ([x - r, y + r], [-r, r]),
+                ([x + r, y + r], [r, r]),
+            ],
+            radius,
+            0.0,
+            color,
+        );
+    }
+
+    // Chevron at the middle of a to b pointing towards b
+    pub fn arrow(&mut self, a: [f64; 2], b: [f64; 2], size: f64, width: f64, color: [f32; 4]) {
+        let length = (b[0] - a[0]).hypot(b[1] - a[1]);
+        if length < f64::EPSILON {
+            return;
+        }
+        let dir = [(b[0] - a[0]) / length, (b[1] - a[1]) / length];
+        let tip = [
+            (a[0] + b[0]) / 2.0 + dir[0] * size / 2.0,
+            (a[1] + b[1]) / 2.0 + dir[1] * size / 2.0,
+        ];
+        let back = [tip[0] - dir[0] * size, tip[1] - dir[1] * size];
+        let side = [-dir[1] * size * 0.6, dir[0] * size * 0.6];
+        let left = [back[0] + side[0], back[1] + side[1]];
+        let right = [back[0] - side[0], back[1] - side[1]];
+        self.polyline(&[left, tip, right], width, color);
+    }
+}
+
+pub struct StrokePainter {
+    program: Program,
+}
+
+impl StrokePainter {
+    pub fn new<F: Facade>(facade: &F) -> Result<Self, ProgramCreationError> {
+        let program = Program::from_source(facade, VERTEX_SHADER, FRAGMENT_SHADER, None)?;
+        Ok(StrokePainter { program })
+    }
+
+    pub fn draw<F: Facade, S: Surface>(
+        &self,
+        facade: &F,
+        target: &mut S,
+        strokes: &Strokes,
+        projection: [[f32; 4]; 4],
+        clip: Option<Rect>,
+    ) {
+        if strokes.is_empty() {
+            return;
+        }
+        let buffer = VertexBuffer::new(facade, strokes.vertices()).unwrap();
+        let uniforms = uniform! {
+            projection: projection,
+        };
+        let params = DrawParameters {
+            blend: Blend::alpha_blending(),
+            scissor: clip,
+            ..Default::default()
+        };
+        target
+            .draw(
+                &buffer,
+                NoIndices(PrimitiveType::TrianglesList),
+                &self.program,
+                &uniforms,
+                &params,
+            )
+            .unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use image::RgbaImage;
+
+use crate::{CancelToken, LineSet, Morph, MorphError, Progress, Vertex};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Spline, // Catmull-Rom through every keyframe, so poses flow into each other
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseIn,    // Starts slowly
+    EaseOut,   // Finishes slowly
+    EaseInOut, // Both, smoothstep
+}
+
+impl Easing {
+    pub fn apply(self, u: f64) -> f64 {
+        match self {
+            Easing::Linear => u,
+            Easing::EaseIn => u * u,
+            Easing::EaseOut => u * (2.0 - u),
+            Easing::EaseInOut => u * u * (3.0 - 2.0 * u),
+        }
+    }
+}
+
+// When one feature line moves within the whole transition, e.g. the eyes from 0 to 0.5 and the
+// mouth from 0.5 to 1, so features can change one after another
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LineTiming {
+    pub start: f64,
+    pub end: f64,
+    pub easing: Easing,
+}
+
+impl Default for LineTiming {
+    fn default() -> Self {
+        LineTiming {
+            start: 0.0,
+            end: 1.0,
+            easing: Easing::Linear,
+        }
+    }
+}
+
+impl LineTiming {
+    pub fn new(start: f64, end: f64, easing: Easing) -> Self {
+        LineTiming { start, end, easing }
+    }
+
+    // The line's own t at the overall t: 0 until start, eased up to 1 at end, or a jump when end
+    // isn't after start. Past 0 and 1 every line follows t itself, so extrapolation keeps its shape
+    pub fn progress(&self, t: f64) -> f64 {
+        if !(0.0..=1.0).contains(&t) {
+            return t;
+        }
+        let u = if self.end <= self.start {
+            if t < self.start {
+                0.0
+            } else {
+                1.0
+            }
+        } else {
+            ((t - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+        };
+        self.easing.apply(u)
+    }
+}
+
+// The whole line set at one moment
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub lines: LineSet,
+}
+
+// Line sets at chosen times with the lines in between interpolated, so a sequence can pass
+// through intermediate poses on its way from the source to the destination
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    keyframes: Vec<Keyframe>, // In time order
+    pub interpolation: Interpolation,
+}
+
+impl Timeline {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Timeline {
+            keyframes: Vec::new(),
+            interpolation,
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    // Adds a keyframe, replacing any already at that time. Every keyframe needs the same lines.
+    // Times that aren't finite have no place in the order and are ignored
+    pub fn insert(&mut self, time: f64, lines: LineSet) {
+        if !time.is_finite() {
+            return;
+        }
+        if let Some(first) = self.keyframes.first() {
+            let shape = |lines: &LineSet| lines.iter().map(Vec::len).collect::<Vec<_>>();
+            assert_eq!(
+                shape(&first.lines),
+                shape(&lines),
+                "keyframes must have matching lines"
+            );
+        }
+        let keyframe = Keyframe { time, lines };
+        match self
+            .keyframes
+            .binary_search_by(|k| k.time.partial_cmp(&time).unwrap())
+        {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    // Times of the first and last keyframes
+    pub fn span(&self) -> Option<(f64, f64)> {
+        Some((self.keyframes.first()?.time, self.keyframes.last()?.time))
+    }
+
+    // Lines at any time, held at the first and last keyframes before and after them. A NaN time
+    // gets the first keyframe's lines
+    pub fn lines_at(&self, time: f64) -> LineSet {
+        let keys = &self.keyframes;
+        let (first, last) = match (keys.first(), keys.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec::new(),
+        };
+        if time <= first.time || time.is_nan() {
+            return first.lines.clone();
+        }
+        if time >= last.time {
+            return last.lines.clone();
+        }
+        let k = keys.iter().rposition(|key| key.time <= time).unwrap();
+        let (start, end) = (&keys[k], &keys[k + 1]);
+        let dt = end.time - start.time;
+        let u = (time - start.time) / dt;
+        let (p0, p1) = (flatten(&start.lines), flatten(&end.lines));
+        let values: Vec<f64> = match self.interpolation {
+            Interpolation::Linear => p0
+                .iter()
+                .zip(p1.iter())
+                .map(|(a, b)| a + (b - a) * u)
+                .collect(),
+            Interpolation::Spline => {
+                // Cubic Hermite with each keyframe's slope taken from its neighbours
+                let (m0, m1) = (self.tangent(k), self.tangent(k + 1));
+                let (u2, u3) = (u * u, u * u * u);
+                let h00 = 2.0 * u3 - 3.0 * u2 + 1.0;
+                let h10 = u3 - 2.0 * u2 + u;
+                let h01 = -2.0 * u3 + 3.0 * u2;
+                let h11 = u3 - u2;
+                (0..p0.len())
+                    .map(|i| h00 * p0[i] + h10 * dt * m0[i] + h01 * p1[i] + h11 * dt * m1[i])
+                    .collect()
+            }
+        };
+        unflatten(&first.lines, &values)
+    }
+
+    // Rate of change of every coordinate at a keyframe, one sided at the ends
+    fn tangent(&self, index: usize) -> Vec<f64> {
+        let keys = &self.keyframes;
+        let before = index.saturating_sub(1);
+        let after = (index + 1).min(keys.len() - 1);
+        let dt = keys[after].time - keys[before].time;
+        flatten(&keys[before].lines)
+            .iter()
+            .zip(flatten(&keys[after].lines).iter())
+            .map(|(a, b)| (b - a) / dt)
+            .collect()
+    }
+
+    // Frames evenly spaced over the timeline, each warped to the lines at its time. The source
+    // and destination lines are the first and last keyframes, and the colours are blended by how
+    // far through the timeline the frame is
+    pub fn render_sequence(
+        &self,
+        src: &RgbaImage,
+        dst: &RgbaImage,
+        frames: usize,
+        (p, a, b): (f64, f64, f64),
+        observer: &mut dyn FnMut(Progress),
+        cancel: &CancelToken,
+    ) -> Result<Vec<RgbaImage>, MorphError> {
+        let (start, end) = match self.span() {
+            Some(span) => span,
+            None => return Ok(Vec::new()),
+        };
+        let (src_lines, dst_lines) = (self.lines_at(start), self.lines_at(end));
+        let mut images = Vec::with_capacity(frames);
+        for frame in 0..frames {
+            let time = if frames > 1 {
+                start + (end - start) * frame as f64 / (frames - 1) as f64
+            } else {
+                start
+            };
+            let t = if end > start {
+                (time - start) / (end - start)
+            } else {
+                0.0
+            };
+            let inter_lines = self.lines_at(time);
+            let morph = Morph::new(src, dst, &src_lines, &dst_lines, (t, p, a, b))
+                .with_inter_lines(&inter_lines);
+            images.push(morph.render_with(observer, cancel)?);
+            observer(Progress::Frame { frame, frames });
+        }
+        Ok(images)
+    }
+}
+
+fn flatten(lines: &[Vec<Vertex>]) -> Vec<f64> {
+    lines
+        .iter()
+        .flat_map(|line| line.iter())
+        .flat_map(|v| v.position.iter().cloned())
+        .collect()
+}
+
+// Coordinates put back into lines shaped like template
+fn unflatten(template: &[Vec<Vertex>], values: &[f64]) -> LineSet {
+    let mut values = values.chunks(2);
+    template
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|_| {
+                    let xy = values.next().unwrap();
+                    Vertex {
+                        position: [xy[0], xy[1]],
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
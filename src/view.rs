@@ -0,0 +1,69 @@
+// Where an image is drawn in the window, mapping between window and image pixel coordinates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pane {
+    pub origin: [f64; 2], // Window position of the image's top left corner
+    pub scale: f64,       // Window units per image pixel
+    pub image_size: (u32, u32),
+}
+
+impl Pane {
+    // Largest placement of the image inside the rectangle that keeps its aspect ratio, centred
+    pub fn fit(image_size: (u32, u32), rect_origin: [f64; 2], rect_size: [f64; 2]) -> Pane {
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        let scale = (rect_size[0] / width).min(rect_size[1] / height);
+        Pane {
+            origin: [
+                rect_origin[0] + (rect_size[0] - width * scale) / 2.0,
+                rect_origin[1] + (rect_size[1] - height * scale) / 2.0,
+            ],
+            scale,
+            image_size,
+        }
+    }
+
+    pub fn to_image(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        [
+            (x - self.origin[0]) / self.scale,
+            (y - self.origin[1]) / self.scale,
+        ]
+    }
+
+    pub fn to_window(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        [
+            self.origin[0] + x * self.scale,
+            self.origin[1] + y * self.scale,
+        ]
+    }
+
+    // Top left and bottom right corners of the image in the window
+    pub fn rect(&self) -> ([f64; 2], [f64; 2]) {
+        (
+            self.origin,
+            self.to_window([self.image_size.0 as f64, self.image_size.1 as f64]),
+        )
+    }
+
+    // Scaled by factor about a window position, so the image point under it stays put
+    pub fn zoom_at(&self, [x, y]: [f64; 2], factor: f64) -> Pane {
+        Pane {
+            origin: [
+                x - (x - self.origin[0]) * factor,
+                y - (y - self.origin[1]) * factor,
+            ],
+            scale: self.scale * factor,
+            image_size: self.image_size,
+        }
+    }
+
+    pub fn panned(&self, [dx, dy]: [f64; 2]) -> Pane {
+        Pane {
+            origin: [self.origin[0] + dx, self.origin[1] + dy],
+            ..*self
+        }
+    }
+
+    pub fn contains(&self, point: [f64; 2]) -> bool {
+        let [x, y] = self.to_image(point);
+        x >= 0.0 && y >= 0.0 && x < self.image_size.0 as f64 && y < self.image_size.1 as f64
+    }
+}
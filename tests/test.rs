@@ -1,5 +1,7 @@
+extern crate glium;
 extern crate image;
 extern crate imagemorph;
+extern crate imageproc;
 
 use image::ImageBuffer;
 use imagemorph::*;
@@ -30,7 +32,7 @@ fn test_inter_lines() {
         &src, &dst, &src_lines, &dst_lines, (0.5f64, 0.0f64, 0.0f64, 0.0f64),
     );
     let inter_line_morph = morph.interpolate_lines();
-    let inter_line_actual = vec![vec![
+    let inter_line_actual = [vec![
         Vertex {
             position: [0f64, 75f64],
         },
@@ -100,8 +102,1287 @@ fn test_warp() {
     let y: f64 = 50.0;
     let new_pt = morph.warp(x, y, &lines, morph.src_lines.to_vec());
     assert_eq!(new_pt.0, 50f64);
-    assert_eq!(new_pt.1, 25f64);
+    assert_eq!(new_pt.1, 75f64);
     let new_pt = morph.warp(x, y, &lines, morph.dst_lines.to_vec());
     assert_eq!(new_pt.0, 50f64);
-    assert_eq!(new_pt.1, 75f64);
+    assert_eq!(new_pt.1, 25f64);
+
+    // Vertical lines move points sideways, by the same rule as horizontal ones move them up
+    let src_lines = vec![vec![
+        Vertex {
+            position: [100f64, 0f64],
+        },
+        Vertex {
+            position: [100f64, 100f64],
+        },
+    ]];
+    let dst_lines = vec![vec![
+        Vertex {
+            position: [50f64, 0f64],
+        },
+        Vertex {
+            position: [50f64, 100f64],
+        },
+    ]];
+    let morph = Morph::new(
+        &src, &dst, &src_lines, &dst_lines, (0.5f64, 0.0f64, 0.0f64, 0.0f64),
+    );
+    let lines = morph.interpolate_lines();
+    let new_pt = morph.warp(x, y, &lines, morph.src_lines.to_vec());
+    assert_eq!(new_pt.0, 75f64);
+    assert_eq!(new_pt.1, 50f64);
+    let new_pt = morph.warp(x, y, &lines, morph.dst_lines.to_vec());
+    assert_eq!(new_pt.0, 25f64);
+    assert_eq!(new_pt.1, 50f64);
+}
+
+// Scatter rectangles of different sizes and shades so FAST finds distinctive corners
+fn synthetic_scene(width: u32, height: u32, shift: (i32, i32)) -> image::RgbaImage {
+    let mut img = ImageBuffer::from_pixel(width, height, image::Rgba([40u8, 40, 40, 255]));
+    let mut seed: u32 = 12345;
+    let mut rand = move |n: u32| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) % n
+    };
+    for _ in 0..40 {
+        let x = rand(width - 40) as i32 + shift.0;
+        let y = rand(height - 40) as i32 + shift.1;
+        let w = 8 + rand(24);
+        let h = 8 + rand(24);
+        let shade = 80 + rand(175) as u8;
+        imageproc::drawing::draw_filled_rect_mut(
+            &mut img,
+            imageproc::rect::Rect::at(x, y).of_size(w, h),
+            image::Rgba([shade, 255 - shade, shade / 2, 255]),
+        );
+    }
+    img
+}
+
+#[test]
+fn test_suggest_lines() {
+    let src = synthetic_scene(320, 320, (0, 0));
+    let dst = synthetic_scene(320, 320, (9, 5));
+    let suggestions = correspondence::suggest_lines(&src, &dst, 10);
+    assert!(!suggestions.is_empty());
+    for suggestion in &suggestions {
+        for k in 0..2 {
+            let src_pt = suggestion.src_line[k].position;
+            let dst_pt = suggestion.dst_line[k].position;
+            assert!((dst_pt[0] - src_pt[0] - 9.0).abs() <= 1.0);
+            assert!((dst_pt[1] - src_pt[1] - 5.0).abs() <= 1.0);
+        }
+    }
+}
+
+#[test]
+fn test_edge_snap() {
+    let mut img = ImageBuffer::from_pixel(100, 100, image::Rgba([0u8, 0, 0, 255]));
+    imageproc::drawing::draw_filled_rect_mut(
+        &mut img,
+        imageproc::rect::Rect::at(40, 40).of_size(30, 30),
+        image::Rgba([255u8, 255, 255, 255]),
+    );
+    let snapper = snap::EdgeSnapper::new(&img);
+    let (x, y) = snapper.snap(44.0, 37.0, 10.0).unwrap();
+    assert!((x - 40.0).abs() <= 2.0 && (y - 40.0).abs() <= 2.0);
+    assert!(snapper.snap(10.0, 10.0, 10.0).is_none());
+    let empty = snap::EdgeSnapper::new(&ImageBuffer::new(0, 0));
+    assert!(empty.snap(0.0, 0.0, 10.0).is_none());
+}
+
+#[test]
+fn test_warp_zero_length_line() {
+    let line = vec![
+        Vertex {
+            position: [20f64, 20f64],
+        },
+        Vertex {
+            position: [80f64, 20f64],
+        },
+    ];
+    let point = vec![
+        Vertex {
+            position: [50f64, 50f64],
+        },
+        Vertex {
+            position: [50f64, 50f64],
+        },
+    ];
+    let src_lines = vec![line.clone(), point.clone()];
+    let dst_lines = vec![line, point];
+    let src = ImageBuffer::new(100, 100);
+    let dst = ImageBuffer::new(100, 100);
+    let morph = Morph::new(
+        &src,
+        &dst,
+        &src_lines,
+        &dst_lines,
+        (0.5f64, 1.0f64, 1.0f64, 1.0f64),
+    );
+    let inter_lines = morph.interpolate_lines();
+    let new_pt = morph.warp(30.0, 60.0, &inter_lines, morph.src_lines.to_vec());
+    assert!((new_pt.0 - 30.0).abs() < 1e-9);
+    assert!((new_pt.1 - 60.0).abs() < 1e-9);
+
+    let only_point = vec![src_lines[1].clone()];
+    let morph = Morph::new(
+        &src,
+        &dst,
+        &only_point,
+        &only_point,
+        (0.5f64, 1.0f64, 1.0f64, 1.0f64),
+    );
+    let new_pt = morph.warp(30.0, 60.0, &only_point, only_point.clone());
+    assert_eq!(new_pt, (30.0, 60.0));
+}
+
+fn line(x0: f64, y0: f64, x1: f64, y1: f64) -> Vec<Vertex> {
+    vec![Vertex { position: [x0, y0] }, Vertex { position: [x1, y1] }]
+}
+
+fn polyline(points: &[[f64; 2]]) -> Vec<Vertex> {
+    points.iter().map(|&position| Vertex { position }).collect()
+}
+
+#[test]
+fn test_feature_line_editing() {
+    let mut lines = FeatureLines::new();
+    lines.add_line(Side::Src, line(0.0, 0.0, 10.0, 0.0));
+    lines.add_line(Side::Src, line(0.0, 20.0, 10.0, 20.0));
+    assert!(lines.complete_lines().is_none());
+    assert_eq!(lines.add_line(Side::Dst, line(5.0, 0.0, 15.0, 0.0)), 0);
+    assert_eq!(lines.add_line(Side::Dst, line(5.0, 20.0, 15.0, 20.0)), 1);
+
+    let (endpoint_line, endpoint) = lines.pick_endpoint(Side::Dst, [14.0, 21.0], 3.0).unwrap();
+    assert_eq!((endpoint_line, endpoint), (1, 1));
+    lines.move_endpoint(Side::Dst, 1, 1, [30.0, 30.0]);
+    assert_eq!(lines.pairs()[1].dst, Some(line(5.0, 20.0, 30.0, 30.0)));
+
+    // Endpoints that don't exist are ignored like lines that don't, with nothing to undo
+    lines.move_endpoint(Side::Dst, 1, 2, [0.0, 0.0]);
+    lines.move_endpoint(Side::Dst, 7, 0, [0.0, 0.0]);
+    assert_eq!(lines.pairs()[1].dst, Some(line(5.0, 20.0, 30.0, 30.0)));
+
+    // Deleting from one image removes the line's pair from the other image too
+    assert_eq!(lines.pick_line(Side::Src, [5.0, 1.0], 3.0), Some(0));
+    lines.delete(0);
+    let (src_lines, dst_lines) = lines.complete_lines().unwrap();
+    assert_eq!(src_lines, vec![line(0.0, 20.0, 10.0, 20.0)]);
+    assert_eq!(dst_lines, vec![line(5.0, 20.0, 30.0, 30.0)]);
+
+    assert!(lines.undo());
+    assert!(lines.undo());
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines.pairs()[1].dst, Some(line(5.0, 20.0, 15.0, 20.0)));
+    assert!(lines.redo());
+    assert!(lines.redo());
+    assert!(!lines.redo());
+    assert_eq!(lines.len(), 1);
+
+    // A new change drops whatever could have been redone
+    assert!(lines.undo());
+    lines.add_pair(line(1.0, 1.0, 2.0, 2.0), line(3.0, 3.0, 4.0, 4.0));
+    assert!(!lines.redo());
+    assert_eq!(lines.len(), 3);
+
+    // Timings stay with their pair, one per straight line, and undo like any other change
+    let early = LineTiming::new(0.0, 0.5, Easing::Linear);
+    lines.set_timing(1, Some(early));
+    lines.set_timing(7, Some(early));
+    assert_eq!(
+        lines.matched_timings(),
+        vec![LineTiming::default(), early, LineTiming::default()]
+    );
+    lines.delete(0);
+    assert_eq!(lines.matched_timings()[0], early);
+    assert!(lines.undo());
+    assert!(lines.undo());
+    assert_eq!(lines.matched_timings(), vec![LineTiming::default(); 3]);
+}
+
+#[test]
+fn test_editor_states() {
+    let state = EditorState::default();
+    assert_eq!(state.editing(), Some(Side::Src));
+    let state = state.next(EditorEvent::SwitchImage);
+    assert_eq!(state, EditorState::Editing(Side::Dst));
+    let state = state.next(EditorEvent::SwitchImage);
+    assert_eq!(state, EditorState::Editing(Side::Src));
+    let state = state
+        .next(EditorEvent::SwitchImage)
+        .next(EditorEvent::TogglePreview);
+    assert_eq!(state, EditorState::Preview(Side::Dst));
+    assert_eq!(state.editing(), None);
+    let state = state.next(EditorEvent::StartRender);
+    assert_eq!(state, EditorState::Rendering(Side::Dst));
+    // Nothing but the end of rendering or cancelling it leaves the rendering state
+    assert_eq!(state.next(EditorEvent::SwitchImage), state);
+    assert_eq!(
+        state.next(EditorEvent::CancelRender),
+        EditorState::Editing(Side::Dst)
+    );
+    let state = state.next(EditorEvent::RenderFinished);
+    assert_eq!(state, EditorState::Preview(Side::Dst));
+    assert_eq!(
+        state.next(EditorEvent::TogglePreview),
+        EditorState::Editing(Side::Dst)
+    );
+}
+
+#[test]
+fn test_pane_mapping() {
+    // A 200x100 image in the right half of a 1024x768 window is letterboxed to 512x256
+    let pane = view::Pane::fit((200, 100), [512.0, 0.0], [512.0, 768.0]);
+    assert_eq!(pane.scale, 2.56);
+    assert_eq!(pane.rect(), ([512.0, 256.0], [1024.0, 512.0]));
+    assert_eq!(pane.to_image([768.0, 384.0]), [100.0, 50.0]);
+    assert_eq!(pane.to_window([100.0, 50.0]), [768.0, 384.0]);
+    assert!(pane.contains([600.0, 300.0]));
+    assert!(!pane.contains([600.0, 100.0]));
+
+    // Zooming keeps the point under the cursor fixed, and clicks map through zoom and pan
+    let zoomed = pane.zoom_at([768.0, 384.0], 4.0);
+    assert_eq!(zoomed.scale, 10.24);
+    assert_eq!(zoomed.to_image([768.0, 384.0]), [100.0, 50.0]);
+    assert_eq!(zoomed.to_image([778.24, 394.24]), [101.0, 51.0]);
+    let panned = zoomed.panned([-10.24, 20.48]);
+    assert_eq!(panned.to_image([768.0, 384.0]), [101.0, 48.0]);
+    assert_eq!(panned.to_window([101.0, 48.0]), [768.0, 384.0]);
+}
+
+#[test]
+fn test_preview_render() {
+    // Only pairs drawn on both images take part, the unfinished third one is left out
+    let mut lines = FeatureLines::new();
+    lines.add_pair(line(10.0, 10.0, 30.0, 10.0), line(10.0, 10.0, 30.0, 10.0));
+    lines.add_pair(line(10.0, 30.0, 30.0, 30.0), line(10.0, 30.0, 30.0, 30.0));
+    lines.add_line(Side::Src, line(0.0, 0.0, 5.0, 5.0));
+    let (src_lines, dst_lines) = lines.matched_lines();
+    assert_eq!(src_lines.len(), 2);
+    assert_eq!(dst_lines.len(), 2);
+
+    // Identical lines leave both images in place, so the result is a plain dissolve, upright
+    let src = synthetic_scene(80, 80, (0, 0));
+    let dst = synthetic_scene(80, 80, (3, 3));
+    for &t in &[0.0, 1.0] {
+        let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (t, 1.0, 1.0, 1.0));
+        let expected = if t == 0.0 { &src } else { &dst };
+        let rendered = morph.render();
+        for (x, y) in [(5, 5), (20, 12), (33, 30), (70, 60)].iter() {
+            assert_eq!(rendered.get_pixel(*x, *y), expected.get_pixel(*x, *y));
+        }
+    }
+}
+
+// Offscreen GL context through Mesa's software renderer, None where it isn't installed
+#[cfg(target_os = "linux")]
+fn headless_gl() -> Option<glium::HeadlessRenderer> {
+    use glium::glutin::platform::unix::HeadlessContextExt;
+    let context = glium::glutin::ContextBuilder::new()
+        .build_osmesa(glium::glutin::dpi::PhysicalSize::new(1, 1))
+        .ok()?;
+    glium::HeadlessRenderer::new(context).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn headless_gl() -> Option<glium::HeadlessRenderer> {
+    None
+}
+
+// Needs the OSMesa library (libosmesa6 on Debian and Ubuntu) and passes without checking
+// anything where it isn't installed
+#[test]
+fn test_gpu_morph_matches_cpu() {
+    let facade = match headless_gl() {
+        Some(facade) => facade,
+        None => {
+            eprintln!("Skipping test_gpu_morph_matches_cpu: no offscreen OpenGL context");
+            return;
+        }
+    };
+    let src = synthetic_scene(96, 80, (0, 0));
+    let dst = synthetic_scene(96, 80, (6, 4));
+    let pairs = vec![line(10.0, 40.0, 60.0, 40.0), line(20.0, 10.0, 70.0, 20.0)];
+    let turned = vec![line(40.0, 20.0, 40.0, 60.0), line(24.0, 12.0, 76.0, 18.0)];
+    let cases = vec![
+        (
+            vec![line(10.0, 10.0, 60.0, 12.0), line(20.0, 60.0, 70.0, 40.0)],
+            vec![line(16.0, 14.0, 66.0, 18.0), line(22.0, 70.0, 80.0, 45.0)],
+            (0.4, 0.5, 1.0, 2.0),
+            LineInterpolation::Endpoints,
+        ),
+        // Past the source, with the lines extrapolated and guarded
+        (
+            pairs.clone(),
+            vec![line(20.0, 40.0, 50.0, 40.0), line(24.0, 12.0, 76.0, 18.0)],
+            (-0.7, 1.0, 1.0, 1.0),
+            LineInterpolation::Endpoints,
+        ),
+        // A line turning a quarter round, half way and past the destination
+        (
+            pairs.clone(),
+            turned.clone(),
+            (0.5, 1.0, 1.0, 1.0),
+            LineInterpolation::CentreAngleLength,
+        ),
+        (
+            pairs.clone(),
+            turned.clone(),
+            (1.4, 1.0, 1.0, 1.0),
+            LineInterpolation::CentreAngleLength,
+        ),
+    ];
+    let staggered = [
+        LineTiming::new(0.0, 0.5, Easing::Linear),
+        LineTiming::new(0.5, 1.0, Easing::EaseInOut),
+    ];
+    let spaces = [
+        BlendSpace::Srgb,
+        BlendSpace::Linear,
+        BlendSpace::Oklab,
+        BlendSpace::Lab,
+    ];
+    let mut gpu = gpu::GpuMorph::new(&facade).unwrap();
+    for (src_lines, dst_lines, params, line_mode) in &cases {
+        for &blend_space in &spaces {
+            let cpu = Morph::new(&src, &dst, src_lines, dst_lines, *params)
+                .with_line_mode(*line_mode)
+                .with_blend_space(blend_space)
+                .render();
+            gpu.line_mode = *line_mode;
+            gpu.blend_space = blend_space;
+            let rendered = gpu.render(&facade, &src, &dst, src_lines, dst_lines, *params);
+
+            // Single precision can land a warped position on the neighbouring pixel now and then
+            let differing = cpu
+                .pixels()
+                .zip(rendered.pixels())
+                .filter(|(c, g)| {
+                    c.0.iter()
+                        .zip(g.0.iter())
+                        .any(|(c, g)| (*c as i32 - *g as i32).abs() > 2)
+                })
+                .count();
+            assert!(
+                differing * 100 < cpu.len() / 4,
+                "{} of {} pixels differ at {:?} with {:?} and {:?}",
+                differing,
+                cpu.len() / 4,
+                params,
+                line_mode,
+                blend_space
+            );
+        }
+    }
+
+    // Each line moving on its own timing, as the CPU version moves them
+    for &line_mode in &[
+        LineInterpolation::Endpoints,
+        LineInterpolation::CentreAngleLength,
+    ] {
+        let params = (0.6, 1.0, 1.0, 1.0);
+        let cpu = Morph::new(&src, &dst, &pairs, &turned, params)
+            .with_line_mode(line_mode)
+            .with_timings(&staggered)
+            .render();
+        gpu.line_mode = line_mode;
+        gpu.blend_space = BlendSpace::default();
+        gpu.timings = staggered.to_vec();
+        let rendered = gpu.render(&facade, &src, &dst, &pairs, &turned, params);
+        let differing = cpu
+            .pixels()
+            .zip(rendered.pixels())
+            .filter(|(c, g)| {
+                c.0.iter()
+                    .zip(g.0.iter())
+                    .any(|(c, g)| (*c as i32 - *g as i32).abs() > 2)
+            })
+            .count();
+        assert!(differing * 100 < cpu.len() / 4);
+    }
+}
+
+#[test]
+fn test_morph_progress() {
+    let src_lines = vec![line(10.0, 10.0, 30.0, 10.0)];
+    let dst_lines = vec![line(12.0, 10.0, 32.0, 14.0)];
+    let src = synthetic_scene(60, 50, (0, 0));
+    let dst = synthetic_scene(60, 50, (3, 3));
+    let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (0.5, 1.0, 1.0, 1.0));
+
+    // Every row of each stage in order, each stage once, and the same image as without an observer
+    let mut reports = Vec::new();
+    let rendered = morph
+        .render_with(&mut |progress| reports.push(progress), &CancelToken::new())
+        .unwrap();
+    assert!(*rendered == *morph.render());
+    assert_eq!(reports.len(), 3 * 49 + 3);
+    assert_eq!(
+        reports[0],
+        Progress::Row {
+            stage: Stage::SrcWarp,
+            row: 0,
+            rows: 49
+        }
+    );
+    assert_eq!(reports[49], Progress::Stage(Stage::SrcWarp));
+    assert_eq!(*reports.last().unwrap(), Progress::Stage(Stage::Blend));
+    let fractions: Vec<f64> = reports.iter().map(Progress::fraction).collect();
+    assert!(fractions.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!((fractions[fractions.len() - 1] - 1.0).abs() < 1e-12);
+
+    // Cancelled up front nothing is reported, cancelled midway it stops within the row
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let mut reported = 0;
+    let result = morph.render_with(&mut |_| reported += 1, &cancel);
+    assert_eq!(result.err(), Some(MorphError::Cancelled));
+    assert_eq!(reported, 0);
+
+    let cancel = CancelToken::new();
+    let mut reports = Vec::new();
+    let result = morph.render_with(
+        &mut |progress| {
+            reports.push(progress);
+            if progress == Progress::Stage(Stage::SrcWarp) {
+                cancel.cancel();
+            }
+        },
+        &cancel,
+    );
+    assert_eq!(result.err(), Some(MorphError::Cancelled));
+    assert_eq!(*reports.last().unwrap(), Progress::Stage(Stage::SrcWarp));
+
+    // morph_with doesn't save the warps, so cancelling it in the blend leaves nothing behind
+    let saved = ["src_warp.png", "dst_warp.png"].map(|path| std::path::Path::new(path).exists());
+    let cancel = CancelToken::new();
+    let result = morph.morph_with(
+        &mut |progress| {
+            if progress == Progress::Stage(Stage::DstWarp) {
+                cancel.cancel();
+            }
+        },
+        &cancel,
+    );
+    assert_eq!(result.err(), Some(MorphError::Cancelled));
+    assert_eq!(
+        ["src_warp.png", "dst_warp.png"].map(|path| std::path::Path::new(path).exists()),
+        saved
+    );
+
+    // Sequences run from the source to the destination and report each frame
+    let mut frames = Vec::new();
+    let sequence = morph
+        .sequence(
+            3,
+            &mut |progress| {
+                if let Progress::Frame {
+                    frame,
+                    frames: count,
+                } = progress
+                {
+                    frames.push((frame, count));
+                }
+            },
+            &CancelToken::new(),
+        )
+        .unwrap();
+    assert_eq!(frames, vec![(0, 3), (1, 3), (2, 3)]);
+    assert!(*sequence[1] == *rendered);
+    let first = Morph::new(&src, &dst, &src_lines, &dst_lines, (0.0, 1.0, 1.0, 1.0));
+    assert!(*sequence[0] == *first.render());
+}
+
+#[test]
+fn test_curves() {
+    // An L shaped polyline against a straight line: both cut at the corner, halfway along
+    let src = polyline(&[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]]);
+    let dst = polyline(&[[0.0, 0.0], [0.0, 40.0]]);
+    let (src_pieces, dst_pieces) = curves::split_pair(&src, &dst);
+    assert_eq!(src_pieces, vec![src[0..2].to_vec(), src[1..3].to_vec()]);
+    assert_eq!(
+        dst_pieces,
+        vec![
+            polyline(&[[0.0, 0.0], [0.0, 20.0]]),
+            polyline(&[[0.0, 20.0], [0.0, 40.0]])
+        ]
+    );
+
+    // Corners of both sides are kept, giving three pieces a side, and straight pairs pass through
+    let dst = polyline(&[[0.0, 0.0], [5.0, 0.0], [20.0, 0.0]]);
+    let (src_lines, dst_lines) = curves::split_lines(
+        &[src.clone(), line(1.0, 2.0, 3.0, 4.0)],
+        &[dst, line(5.0, 6.0, 7.0, 8.0)],
+    );
+    assert_eq!(src_lines.len(), 4);
+    assert_eq!(dst_lines.len(), 4);
+    assert_eq!(src_lines[0], polyline(&[[0.0, 0.0], [5.0, 0.0]]));
+    assert_eq!(dst_lines[1], polyline(&[[5.0, 0.0], [10.0, 0.0]]));
+    assert_eq!(dst_lines[3], line(5.0, 6.0, 7.0, 8.0));
+
+    // FeatureLines hands Morph the pieces while keeping the polyline as drawn
+    let mut lines = FeatureLines::new();
+    lines.add_pair(src.clone(), polyline(&[[0.0, 0.0], [0.0, 40.0]]));
+    let (complete_src, complete_dst) = lines.complete_lines().unwrap();
+    assert_eq!(complete_src.len(), 2);
+    assert_eq!(complete_dst.len(), 2);
+    assert_eq!(lines.pairs()[0].src.as_ref(), Some(&src));
+    assert_eq!(lines.pick_line(Side::Src, [11.0, 8.0], 2.0), Some(0));
+
+    // Curves pass through every knot, and straight runs simplify to their ends
+    let knots = [[0.0, 0.0], [10.0, 10.0], [20.0, 0.0]];
+    let curve = curves::bezier(&curves::smooth(&knots), 8);
+    assert_eq!(curve.len(), 17);
+    for (point, knot) in curve.iter().step_by(8).zip(knots.iter()) {
+        assert!((point[0] - knot[0]).abs() < 1e-9 && (point[1] - knot[1]).abs() < 1e-9);
+    }
+    let path = [[0.0, 0.0], [1.0, 0.1], [2.0, 0.0], [3.0, 5.0], [4.0, 10.0]];
+    assert_eq!(
+        curves::simplify(&path, 0.5),
+        vec![[0.0, 0.0], [2.0, 0.0], [4.0, 10.0]]
+    );
+}
+
+#[test]
+fn test_multi_morph() {
+    let src_lines = vec![line(10.0, 10.0, 30.0, 10.0), line(10.0, 40.0, 40.0, 30.0)];
+    let dst_lines = vec![line(14.0, 12.0, 32.0, 16.0), line(8.0, 36.0, 44.0, 34.0)];
+    let src = synthetic_scene(60, 50, (0, 0));
+    let dst = synthetic_scene(60, 50, (3, 3));
+
+    // Two images weighted 3:1 are the ordinary morph a quarter of the way along
+    let images = vec![src.clone(), dst.clone()];
+    let lines = vec![src_lines.clone(), dst_lines.clone()];
+    let multi = MultiMorph::new(&images, &lines, &[3.0, 1.0], (1.0, 1.0, 1.0));
+    assert_eq!(multi.weights, vec![0.75, 0.25]);
+    let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (0.25, 1.0, 1.0, 1.0));
+    assert_eq!(multi.average_lines(), morph.interpolate_lines());
+    assert!(*multi.render() == *morph.render());
+
+    // Three images drawn with the same lines aren't moved, so the average face is a plain mean,
+    // of the light by default and of the encoded values when asked
+    let third = synthetic_scene(60, 50, (6, 1));
+    let images = vec![src.clone(), dst.clone(), third.clone()];
+    let lines = vec![src_lines.clone(), src_lines.clone(), src_lines.clone()];
+    let average = MultiMorph::average(&images, &lines, (1.0, 1.0, 1.0));
+    assert_eq!(average.average_lines(), src_lines);
+    let rendered = average.render();
+    let encoded = MultiMorph::average(&images, &lines, (1.0, 1.0, 1.0))
+        .with_blend_space(BlendSpace::Srgb)
+        .render();
+    for (x, y) in [(5, 5), (20, 12), (33, 30), (50, 40)].iter() {
+        let values: Vec<f64> = images
+            .iter()
+            .map(|img| img.get_pixel(*x, *y)[0] as f64)
+            .collect();
+        let light: f64 = values.iter().map(|&v| color_space::srgb_to_linear(v)).sum();
+        let mean = rendered.get_pixel(*x, *y)[0] as f64;
+        assert!((mean - color_space::linear_to_srgb(light / 3.0)).abs() <= 1.0);
+        let mean = encoded.get_pixel(*x, *y)[0] as f64;
+        assert!((mean - (values.iter().sum::<f64>() / 3.0).floor()).abs() <= 1.0);
+    }
+
+    // Blending empty warps gives an empty image
+    let empty: Vec<image::RgbaImage> = vec![ImageBuffer::new(0, 0); 3];
+    assert_eq!(average.blend(&empty).dimensions(), (0, 0));
+
+    // Barycentric weights: corners, the centre, and outside points pulled onto an edge
+    let triangle = [[0.0, 0.0], [3.0, 0.0], [0.0, 3.0]];
+    assert_eq!(multi::barycentric(triangle, [3.0, 0.0]), [0.0, 1.0, 0.0]);
+    let centre = multi::barycentric(triangle, [1.0, 1.0]);
+    assert!(centre.iter().all(|w| (w - 1.0 / 3.0).abs() < 1e-12));
+    assert_eq!(multi::barycentric(triangle, [1.5, -4.0]), [0.5, 0.5, 0.0]);
+}
+
+#[test]
+fn test_extrapolation() {
+    let src_lines = vec![line(10.0, 40.0, 60.0, 40.0), line(20.0, 10.0, 40.0, 10.0)];
+    let dst_lines = vec![line(20.0, 40.0, 50.0, 40.0), line(20.0, 14.0, 44.0, 14.0)];
+    let src = synthetic_scene(80, 80, (0, 0));
+    let dst = synthetic_scene(80, 80, (3, 3));
+
+    // Past the destination lines carry on in a straight line while they keep their length
+    let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (2.0, 1.0, 1.0, 1.0));
+    let inter = morph.interpolate_lines();
+    assert_eq!(inter[0], line(30.0, 40.0, 40.0, 40.0));
+    assert_eq!(inter[1], line(20.0, 18.0, 48.0, 18.0));
+
+    // Further on the first line would flip round, so it stays a quarter of the destination's
+    // length about its middle, pointing the same way
+    let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (3.0, 1.0, 1.0, 1.0));
+    assert_eq!(morph.interpolate_lines()[0], line(31.25, 40.0, 38.75, 40.0));
+    let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (-3.0, 1.0, 1.0, 1.0));
+    let inter = morph.interpolate_lines();
+    assert!(inter[0][1].position[0] - inter[0][0].position[0] >= 50.0);
+
+    // Colours past either image stay that image's, with identical lines leaving them in place
+    for &(t, expected) in &[(-0.5, &src), (1.5, &dst)] {
+        let morph = Morph::new(&src, &dst, &src_lines, &src_lines, (t, 1.0, 1.0, 1.0));
+        let rendered = morph.render();
+        for (x, y) in [(5, 5), (20, 12), (33, 30), (70, 60)].iter() {
+            assert_eq!(rendered.get_pixel(*x, *y), expected.get_pixel(*x, *y));
+        }
+    }
+
+    // A caricature doubles the face's differences from the reference at amount 1
+    let face = caricature(&dst, &dst_lines, &src, &src_lines, 1.0, (1.0, 1.0, 1.0));
+    assert_eq!(face.t, 2.0);
+    assert_eq!(face.interpolate_lines()[1], line(20.0, 18.0, 48.0, 18.0));
+}
+
+#[test]
+fn test_timeline() {
+    let start = vec![line(10.0, 10.0, 30.0, 10.0), line(10.0, 40.0, 40.0, 40.0)];
+    let pose = vec![line(10.0, 20.0, 30.0, 20.0), line(20.0, 40.0, 50.0, 30.0)];
+    let end = vec![line(14.0, 12.0, 34.0, 16.0), line(10.0, 36.0, 44.0, 34.0)];
+
+    // Two keyframes interpolate like Morph whichever interpolation is used
+    for &interpolation in &[Interpolation::Linear, Interpolation::Spline] {
+        let mut timeline = Timeline::new(interpolation);
+        timeline.insert(1.0, end.clone());
+        timeline.insert(0.0, start.clone());
+        timeline.insert(f64::NAN, pose.clone());
+        timeline.insert(f64::INFINITY, pose.clone());
+        assert_eq!(timeline.span(), Some((0.0, 1.0)));
+        let src = synthetic_scene(60, 50, (0, 0));
+        let morph = Morph::new(&src, &src, &start, &end, (0.25, 1.0, 1.0, 1.0));
+        let expected = morph.interpolate_lines();
+        for (line, other) in timeline.lines_at(0.25).iter().zip(expected.iter()) {
+            for (a, b) in line.iter().zip(other.iter()) {
+                assert!((a.position[0] - b.position[0]).abs() < 1e-9);
+                assert!((a.position[1] - b.position[1]).abs() < 1e-9);
+            }
+        }
+    }
+
+    // With a pose in between the lines pass through it, and stay put outside the keyframes
+    let mut linear = Timeline::new(Interpolation::Linear);
+    let mut spline = Timeline::new(Interpolation::Spline);
+    for timeline in [&mut linear, &mut spline].iter_mut() {
+        timeline.insert(0.0, start.clone());
+        timeline.insert(1.0, start.clone());
+        timeline.insert(0.5, pose.clone());
+        timeline.insert(1.0, end.clone());
+        assert_eq!(timeline.keyframes().len(), 3);
+        assert_eq!(timeline.span(), Some((0.0, 1.0)));
+        assert_eq!(timeline.lines_at(0.5), pose);
+        assert_eq!(timeline.lines_at(-1.0), start);
+        assert_eq!(timeline.lines_at(2.0), end);
+        assert_eq!(timeline.lines_at(f64::NAN), start);
+        assert_eq!(timeline.lines_at(f64::INFINITY), end);
+    }
+    assert_eq!(linear.lines_at(0.25)[0], line(10.0, 15.0, 30.0, 15.0));
+    // The spline eases through the pose rather than turning sharply at it
+    let eased = spline.lines_at(0.25)[0][0].position;
+    assert!(eased[1] > 15.0 && eased[1] < 20.0);
+
+    // Each frame of a sequence is warped to the timeline and blended by its place in it
+    let src = synthetic_scene(60, 50, (0, 0));
+    let dst = synthetic_scene(60, 50, (3, 3));
+    let frames = linear
+        .render_sequence(
+            &src,
+            &dst,
+            3,
+            (1.0, 1.0, 1.0),
+            &mut |_| (),
+            &CancelToken::new(),
+        )
+        .unwrap();
+    assert_eq!(frames.len(), 3);
+    let middle = Morph::new(&src, &dst, &start, &end, (0.5, 1.0, 1.0, 1.0)).with_inter_lines(&pose);
+    assert!(*frames[1] == *middle.render());
+    let first = Morph::new(&src, &dst, &start, &end, (0.0, 1.0, 1.0, 1.0));
+    assert!(*frames[0] == *first.render());
+}
+
+#[test]
+fn test_line_timing() {
+    let eyes = LineTiming::new(0.0, 0.5, Easing::Linear);
+    let mouth = LineTiming::new(0.5, 1.0, Easing::EaseInOut);
+    assert_eq!(eyes.progress(0.25), 0.5);
+    assert_eq!(eyes.progress(0.75), 1.0);
+    assert_eq!(mouth.progress(0.25), 0.0);
+    assert_eq!(mouth.progress(0.75), 0.5);
+    assert!(mouth.progress(0.6) < 0.2);
+    assert_eq!(Easing::EaseIn.apply(0.5), 0.25);
+    assert_eq!(Easing::EaseOut.apply(0.5), 0.75);
+    assert_eq!(LineTiming::new(0.5, 0.5, Easing::Linear).progress(0.4), 0.0);
+    // Extrapolation isn't staggered
+    assert_eq!(mouth.progress(1.5), 1.5);
+
+    // Halfway through, the eyes have finished while the mouth hasn't started
+    let src_lines = vec![line(10.0, 10.0, 30.0, 10.0), line(10.0, 40.0, 40.0, 40.0)];
+    let dst_lines = vec![line(14.0, 14.0, 34.0, 14.0), line(10.0, 30.0, 40.0, 30.0)];
+    let src = synthetic_scene(60, 50, (0, 0));
+    let timings = [eyes, mouth];
+    let morph =
+        Morph::new(&src, &src, &src_lines, &dst_lines, (0.5, 1.0, 1.0, 1.0)).with_timings(&timings);
+    assert_eq!(morph.line_t(0), 1.0);
+    assert_eq!(
+        morph.interpolate_lines(),
+        vec![dst_lines[0].clone(), src_lines[1].clone()]
+    );
+
+    // Lines without a timing follow t
+    let morph = Morph::new(&src, &src, &src_lines, &dst_lines, (0.25, 1.0, 1.0, 1.0))
+        .with_timings(&timings[..1]);
+    assert_eq!(morph.line_t(1), 0.25);
+    assert_eq!(morph.interpolate_lines()[1], line(10.0, 37.5, 40.0, 37.5));
+
+    // Every straight piece of a curved feature keeps the feature's timing, and the features
+    // after it keep theirs
+    let src_features = vec![
+        polyline(&[[10.0, 10.0], [20.0, 5.0], [30.0, 10.0]]),
+        src_lines[1].clone(),
+    ];
+    let dst_features = vec![
+        polyline(&[[14.0, 14.0], [24.0, 9.0], [34.0, 14.0]]),
+        dst_lines[1].clone(),
+    ];
+    let (src_lines, dst_lines) = curves::split_lines(&src_features, &dst_features);
+    assert_eq!(src_lines.len(), 3);
+    let split = curves::split_timings(&src_features, &dst_features, &timings);
+    assert_eq!(split, vec![eyes, eyes, mouth]);
+    let morph =
+        Morph::new(&src, &src, &src_lines, &dst_lines, (0.5, 1.0, 1.0, 1.0)).with_timings(&split);
+    assert_eq!(
+        morph.interpolate_lines(),
+        vec![
+            dst_lines[0].clone(),
+            dst_lines[1].clone(),
+            src_lines[2].clone()
+        ]
+    );
+    let split = curves::split_timings(&src_features, &dst_features, &timings[..1]);
+    assert_eq!(split, vec![eyes, eyes, LineTiming::default()]);
+}
+
+#[test]
+fn test_rotating_lines() {
+    let close = |a: &[Vertex], b: &[Vertex]| {
+        a.iter().zip(b.iter()).all(|(a, b)| {
+            (a.position[0] - b.position[0]).abs() < 1e-9
+                && (a.position[1] - b.position[1]).abs() < 1e-9
+        })
+    };
+    let length = |l: &[Vertex]| {
+        (l[1].position[0] - l[0].position[0]).hypot(l[1].position[1] - l[0].position[1])
+    };
+    let src = synthetic_scene(80, 80, (0, 0));
+
+    // A quarter turn about the middle: endpoints cut the corner and shorten the line halfway,
+    // turning keeps its length and its middle
+    let src_lines = vec![line(20.0, 40.0, 60.0, 40.0)];
+    let dst_lines = vec![line(40.0, 20.0, 40.0, 60.0)];
+    let morph = Morph::new(&src, &src, &src_lines, &dst_lines, (0.5, 1.0, 1.0, 1.0));
+    let straight = morph.interpolate_lines();
+    assert!(length(&straight[0]) < 30.0);
+    let turned = morph
+        .with_line_mode(LineInterpolation::CentreAngleLength)
+        .interpolate_lines();
+    let half = 20.0 / 2f64.sqrt();
+    assert!(close(
+        &turned[0],
+        &line(40.0 - half, 40.0 - half, 40.0 + half, 40.0 + half)
+    ));
+
+    // Both ends of the turn are the lines themselves
+    for &(t, expected) in &[(0.0, &src_lines), (1.0, &dst_lines)] {
+        let morph = Morph::new(&src, &src, &src_lines, &dst_lines, (t, 1.0, 1.0, 1.0))
+            .with_line_mode(LineInterpolation::CentreAngleLength);
+        assert!(close(&morph.interpolate_lines()[0], &expected[0]));
+    }
+
+    // Turning the short way across +-180 degrees while the length changes evenly
+    let src_lines = vec![line(
+        50.0,
+        20.0,
+        10.0,
+        20.0 + 40.0 * 10f64.to_radians().tan(),
+    )];
+    let dst_lines = vec![line(
+        40.0,
+        30.0,
+        20.0,
+        30.0 - 20.0 * 10f64.to_radians().tan(),
+    )];
+    let morph = Morph::new(&src, &src, &src_lines, &dst_lines, (0.5, 1.0, 1.0, 1.0))
+        .with_line_mode(LineInterpolation::CentreAngleLength);
+    let turned = &morph.interpolate_lines()[0];
+    let expected_length = (length(&src_lines[0]) + length(&dst_lines[0])) / 2.0;
+    assert!((length(turned) - expected_length).abs() < 1e-9);
+    assert!((turned[0].position[1] - turned[1].position[1]).abs() < 1e-9);
+    assert!(turned[1].position[0] < turned[0].position[0]);
+
+    // Far past the destination the line keeps turning but never shrinks away
+    let shrinking = vec![line(30.0, 40.0, 50.0, 40.0)];
+    let morph = Morph::new(&src, &src, &src_lines, &shrinking, (4.0, 1.0, 1.0, 1.0))
+        .with_line_mode(LineInterpolation::CentreAngleLength);
+    assert!(length(&morph.interpolate_lines()[0]) >= 0.25 * 20.0 - 1e-9);
+}
+
+#[test]
+fn test_warp_field() {
+    let src = synthetic_scene(60, 50, (0, 0));
+    let dst = synthetic_scene(60, 50, (4, 3));
+    let src_lines = vec![line(15.0, 10.0, 45.0, 12.0), line(20.0, 40.0, 40.0, 25.0)];
+    let dst_lines = vec![line(19.0, 13.0, 49.0, 15.0), line(24.0, 43.0, 44.0, 28.0)];
+    let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (0.4, 0.5, 1.0, 2.0));
+
+    // Applying the field is the same warp
+    let field = WarpField::from_morph(&morph, Side::Src);
+    assert!(*field.apply(&src) == *morph.warp_image(&src, &src_lines));
+    let field = WarpField::from_morph(&morph, Side::Dst);
+    assert!(*field.apply(&dst) == *morph.warp_image(&dst, &dst_lines));
+
+    let dir = std::env::temp_dir().join(format!("imagemorph_field_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let near = |a: &WarpField, b: &WarpField, tolerance: f64| {
+        (a.width, a.height) == (b.width, b.height)
+            && a.positions
+                .iter()
+                .zip(b.positions.iter())
+                .all(|(p, q)| (p[0] - q[0]).abs() <= tolerance && (p[1] - q[1]).abs() <= tolerance)
+    };
+
+    // Floats survive the flow and TIFF files, 16 bits keep the PNG within a small part of a pixel
+    field.write_flo(dir.join("field.flo")).unwrap();
+    assert!(near(
+        &WarpField::read_flo(dir.join("field.flo")).unwrap(),
+        &field,
+        1e-3
+    ));
+    field.write_stmap_tiff(dir.join("field.tif")).unwrap();
+    assert!(near(
+        &WarpField::read_stmap_tiff(dir.join("field.tif")).unwrap(),
+        &field,
+        1e-3
+    ));
+    let inside = WarpField {
+        positions: field
+            .positions
+            .iter()
+            .map(|p| [p[0].clamp(-0.5, 59.5), p[1].clamp(-0.5, 49.5)])
+            .collect(),
+        ..field.clone()
+    };
+    inside.write_stmap_png(dir.join("field.png")).unwrap();
+    assert!(near(
+        &WarpField::read_stmap_png(dir.join("field.png")).unwrap(),
+        &inside,
+        1e-3
+    ));
+
+    // Every pixel of the identity samples itself, away from the transparent border
+    let identity = WarpField::identity(60, 50);
+    let copy = identity.apply(&src);
+    assert_eq!(identity.get(7, 9), [7.0, 9.0]);
+    assert_eq!(copy.get_pixel(30, 20), src.get_pixel(30, 20));
+    identity.write_stmap_tiff(dir.join("identity.tif")).unwrap();
+    assert!(near(
+        &WarpField::read_stmap_tiff(dir.join("identity.tif")).unwrap(),
+        &identity,
+        1e-4
+    ));
+
+    // Other files are refused
+    assert!(WarpField::read_flo(dir.join("field.png")).is_err());
+    assert!(WarpField::read_stmap_tiff(dir.join("field.flo")).is_err());
+
+    // Headers claiming more than the file holds are errors, not overflows or huge allocations
+    let mut flo = 202_021.25f32.to_le_bytes().to_vec();
+    flo.extend_from_slice(&i32::MAX.to_le_bytes());
+    flo.extend_from_slice(&i32::MAX.to_le_bytes());
+    std::fs::write(dir.join("huge.flo"), &flo).unwrap();
+    assert!(WarpField::read_flo(dir.join("huge.flo")).is_err());
+    let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+    let entries: [(u16, u16, u32, [u8; 4]); 7] = [
+        (256, 4, 1, 0x1_0000u32.to_le_bytes()),
+        (257, 4, 1, 0x1_0000u32.to_le_bytes()),
+        (258, 3, 2, [32, 0, 32, 0]),
+        (277, 3, 1, [2, 0, 0, 0]),
+        (339, 3, 2, [3, 0, 3, 0]),
+        (273, 4, 1, 0xffff_fff0u32.to_le_bytes()),
+        (279, 4, 1, 0x100u32.to_le_bytes()),
+    ];
+    tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, kind, count, value) in entries.iter() {
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&kind.to_le_bytes());
+        tiff.extend_from_slice(&count.to_le_bytes());
+        tiff.extend_from_slice(value);
+    }
+    std::fs::write(dir.join("huge.tif"), &tiff).unwrap();
+    assert!(WarpField::read_stmap_tiff(dir.join("huge.tif")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_reused_warp_fields() {
+    let src = synthetic_scene(60, 50, (0, 0));
+    let dst = synthetic_scene(60, 50, (4, 3));
+    let src_lines = vec![line(15.0, 10.0, 45.0, 12.0), line(20.0, 40.0, 40.0, 25.0)];
+    let dst_lines = vec![line(19.0, 13.0, 49.0, 15.0), line(24.0, 43.0, 44.0, 28.0)];
+    let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (0.4, 0.5, 1.0, 2.0));
+
+    // Whole pixel positions copy pixels, half way between two gives their average
+    let mut field = WarpField::identity(60, 50);
+    assert!(*field.sample(&src) == *src);
+    let (a, b) = (src.get_pixel(10, 20), src.get_pixel(11, 20));
+    field.positions[20 * 60 + 10] = [10.5, 20.0];
+    let mixed = field.sample(&src);
+    for c in 0..3 {
+        let average = (a[c] as f64 + b[c] as f64) / 2.0;
+        assert!((mixed.get_pixel(10, 20)[c] as f64 - average).abs() <= 0.5);
+    }
+    field.positions[0] = [-3.0, 0.0];
+    assert_eq!(field.sample(&src).get_pixel(0, 0)[3], 0);
+
+    // Images of another size are read where the positions fall on them
+    let small = ImageBuffer::from_fn(30, 25, |x, y| {
+        image::Rgba([x as u8 * 8, y as u8 * 9, 90, 255])
+    });
+    let sampled = field.sample(&small);
+    assert_eq!(sampled.dimensions(), (60, 50));
+    assert_eq!(sampled.get_pixel(5, 5), small.get_pixel(5, 5));
+    assert_eq!(sampled.get_pixel(40, 40)[3], 0);
+    assert_eq!(field.sample(&ImageBuffer::new(0, 0)).get_pixel(5, 5)[3], 0);
+    let applied = field.apply(&ImageBuffer::new(0, 0));
+    assert_eq!(applied.dimensions(), (60, 50));
+    assert!(applied.pixels().all(|pixel| pixel[3] == 0));
+
+    // The same fields render retouched images without the lines
+    let fields = morph.warp_fields();
+    let (new_src, new_dst) = (
+        synthetic_scene(60, 50, (2, 0)),
+        synthetic_scene(60, 50, (0, 2)),
+    );
+    let retouched = Morph::new(
+        &new_src,
+        &new_dst,
+        &src_lines,
+        &dst_lines,
+        (0.4, 0.5, 1.0, 2.0),
+    );
+    assert!(
+        *morph.render_fields(&fields, &new_src, &new_dst)
+            == *retouched.render_fields(&retouched.warp_fields(), &new_src, &new_dst)
+    );
+    assert!(
+        *morph.render_fields(&fields, &new_src, &new_dst)
+            != *morph.render_fields(&fields, &src, &dst)
+    );
+
+    // Keys follow the geometry and parameters but not the pixels, and don't change between runs
+    let key = WarpField::cache_key(&morph, Side::Src);
+    assert_eq!(key, 0x813c_7651_0af6_18df);
+    assert_eq!(key, WarpField::cache_key(&retouched, Side::Src));
+    assert_ne!(key, WarpField::cache_key(&morph, Side::Dst));
+    let later = Morph::new(&src, &dst, &src_lines, &dst_lines, (0.5, 0.5, 1.0, 2.0));
+    assert_ne!(key, WarpField::cache_key(&later, Side::Src));
+    let moved = vec![line(15.0, 10.0, 45.0, 12.5), src_lines[1].clone()];
+    let moved = Morph::new(&src, &dst, &moved, &dst_lines, (0.4, 0.5, 1.0, 2.0));
+    assert_ne!(key, WarpField::cache_key(&moved, Side::Src));
+    let turning = Morph::new(&src, &dst, &src_lines, &dst_lines, (0.4, 0.5, 1.0, 2.0))
+        .with_line_mode(LineInterpolation::CentreAngleLength);
+    assert_ne!(key, WarpField::cache_key(&turning, Side::Src));
+
+    // The first call saves the field, the second reads back exactly what the first returned
+    let dir = std::env::temp_dir().join(format!("imagemorph_cache_{}", std::process::id()));
+    let computed = WarpField::cached(&morph, Side::Src, &dir).unwrap();
+    assert!(computed
+        .positions
+        .iter()
+        .zip(fields.0.positions.iter())
+        .all(|(p, q)| (p[0] - q[0]).abs() < 1e-3 && (p[1] - q[1]).abs() < 1e-3));
+    let path = dir.join(format!("{:016x}.flo", key));
+    assert!(path.exists());
+    assert_eq!(
+        WarpField::cached(&retouched, Side::Src, &dir).unwrap(),
+        computed
+    );
+
+    // A file cut short, e.g. by a crash while writing it, is a miss and gets replaced
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    assert_eq!(
+        WarpField::cached(&morph, Side::Src, &dir).unwrap(),
+        computed
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_morph_layers() {
+    let src = synthetic_scene(60, 50, (0, 0));
+    let dst = synthetic_scene(60, 50, (4, 3));
+    let src_lines = vec![line(15.0, 10.0, 45.0, 12.0), line(20.0, 40.0, 40.0, 25.0)];
+    let dst_lines = vec![line(19.0, 13.0, 49.0, 15.0), line(24.0, 43.0, 44.0, 28.0)];
+
+    // Label maps keep only the values they started with, split down the middle of each image
+    let labels = |split: u32| {
+        ImageBuffer::from_fn(60, 50, |x, _| {
+            image::Rgba(if x < split {
+                [1u8, 0, 0, 255]
+            } else {
+                [7, 0, 0, 255]
+            })
+        })
+    };
+    let (src_labels, dst_labels) = (labels(30), labels(34));
+    let matte = ImageBuffer::from_pixel(60, 50, image::Rgba([200u8, 200, 200, 255]));
+    let layers = [
+        Layer::new(&src_labels, &dst_labels, Sampling::Nearest),
+        Layer::new(&src_labels, &dst_labels, Sampling::Bilinear),
+        Layer::new(&matte, &matte, Sampling::Bilinear),
+    ];
+
+    for &t in &[0.3, 0.7] {
+        let morph = Morph::new(&src, &dst, &src_lines, &dst_lines, (t, 0.5, 1.0, 2.0));
+        let (colour, warped) = morph.render_layers(&layers);
+        assert!(*colour == *morph.render());
+        assert_eq!(warped.len(), 3);
+
+        // Nearest layers are the closer image's labels warped exactly like its colours
+        let expected = if t < 0.5 {
+            morph.warp_image(&src_labels, &src_lines)
+        } else {
+            morph.warp_image(&dst_labels, &dst_lines)
+        };
+        assert!(*warped[0] == *expected);
+        assert!(warped[0].pixels().all(|p| [0, 1, 7].contains(&p[0])));
+
+        // Bilinear layers are smooth, mixing labels across the edge but leaving flat areas flat
+        assert!(warped[1]
+            .pixels()
+            .any(|p| p[3] == 255 && p[0] > 1 && p[0] < 7));
+        assert_eq!(warped[2].get_pixel(30, 25)[0], 200);
+    }
+}
+
+#[test]
+fn test_blend_spaces() {
+    // Every 8 bit value comes back unchanged from linear light and the perceptual spaces
+    let spaces = [
+        BlendSpace::Srgb,
+        BlendSpace::Linear,
+        BlendSpace::Oklab,
+        BlendSpace::Lab,
+    ];
+    for v in 0..=255 {
+        let v = v as f64;
+        assert_eq!(
+            color_space::linear_to_srgb(color_space::srgb_to_linear(v)),
+            v
+        );
+        let rgb = [v, 255.0 - v, (v * 7.0) % 256.0];
+        for &space in &spaces {
+            let mixed = space.mix(&[(space.decode(rgb), 1.0)]);
+            assert!(mixed
+                .iter()
+                .zip(rgb.iter())
+                .all(|(m, c)| (m - c).abs() < 1e-6));
+        }
+    }
+
+    // Half way from black to white: the encoded values give a dark grey, linear light is half
+    // the light and the perceptual spaces sit between, looking half way
+    let black = ImageBuffer::from_pixel(50, 50, image::Rgba([0u8, 0, 0, 255]));
+    let white = ImageBuffer::from_pixel(50, 50, image::Rgba([255u8, 255, 255, 255]));
+    let grey = |space| {
+        Morph::new(&black, &white, &[], &[], (0.5, 1.0, 1.0, 1.0))
+            .with_blend_space(space)
+            .render()
+            .get_pixel(20, 20)[0]
+    };
+    assert_eq!(grey(BlendSpace::Srgb), 127);
+    assert_eq!(grey(BlendSpace::Linear), 188);
+    assert_eq!(
+        Morph::new(&black, &white, &[], &[], (0.5, 1.0, 1.0, 1.0))
+            .render()
+            .get_pixel(20, 20)[0],
+        188
+    );
+    for &space in &[BlendSpace::Oklab, BlendSpace::Lab] {
+        assert!(grey(space) < grey(BlendSpace::Srgb));
+    }
+
+    // Red to green doesn't pass through a muddy brown in linear light
+    let red = ImageBuffer::from_pixel(50, 50, image::Rgba([255u8, 0, 0, 255]));
+    let green = ImageBuffer::from_pixel(50, 50, image::Rgba([0u8, 255, 0, 255]));
+    let middle = |space| {
+        let morph =
+            Morph::new(&red, &green, &[], &[], (0.5, 1.0, 1.0, 1.0)).with_blend_space(space);
+        let pixel = *morph.render().get_pixel(20, 20);
+        pixel[0] as u32 + pixel[1] as u32
+    };
+    assert!(middle(BlendSpace::Linear) > middle(BlendSpace::Srgb) + 100);
+}
+
+#[test]
+fn test_color_transfer() {
+    // A dark and a bright photo of the same gradient, as if lit differently
+    let dark = ImageBuffer::from_fn(64, 48, |x, y| {
+        image::Rgba([20 + x as u8, 30 + y as u8, 10 + (x + y) as u8 / 2, 255])
+    });
+    let bright = ImageBuffer::from_fn(64, 48, |x, y| {
+        image::Rgba([
+            150 + x as u8 * 3 / 2,
+            140 + y as u8 * 2,
+            120 + (x + y) as u8,
+            255,
+        ])
+    });
+    let mean = |img: &image::RgbaImage, c: usize| {
+        img.pixels().map(|p| p[c] as f64).sum::<f64>() / (img.width() * img.height()) as f64
+    };
+    for &method in &[ColorTransfer::Reinhard, ColorTransfer::Histogram] {
+        // Half way both move to the same colours
+        let (src, dst) =
+            color_transfer::transfer(method, &dark, &bright, None, 0.5, BlendSpace::Linear);
+        for c in 0..3 {
+            let before = mean(&bright, c) - mean(&dark, c);
+            let after = mean(&dst, c) - mean(&src, c);
+            assert!(after.abs() < before.abs() / 20.0);
+            assert!(mean(&src, c) > mean(&dark, c) && mean(&dst, c) < mean(&bright, c));
+        }
+
+        // At the source the source keeps its colours and the destination takes them on
+        let (src, dst) =
+            color_transfer::transfer(method, &dark, &bright, None, 0.0, BlendSpace::Linear);
+        assert!(src
+            .pixels()
+            .zip(dark.pixels())
+            .all(|(a, b)| (0..4).all(|c| (a[c] as i32 - b[c] as i32).abs() <= 1)));
+        for c in 0..3 {
+            assert!((mean(&dst, c) - mean(&dark, c)).abs() < 2.0);
+        }
+
+        // A mask over the left half leaves the right half alone
+        let mask = ImageBuffer::from_fn(64, 48, |x, _| {
+            image::Rgba(if x < 32 { [255u8; 4] } else { [0, 0, 0, 255] })
+        });
+        let (src, dst) = color_transfer::transfer(
+            method,
+            &dark,
+            &bright,
+            Some((&mask, &mask)),
+            0.5,
+            BlendSpace::Linear,
+        );
+        for (x, y, pixel) in dst.enumerate_pixels() {
+            if x >= 32 {
+                assert_eq!(pixel, bright.get_pixel(x, y));
+                assert_eq!(src.get_pixel(x, y), dark.get_pixel(x, y));
+            } else {
+                assert!(pixel[0] < bright.get_pixel(x, y)[0]);
+            }
+        }
+
+        // The morph transfers its warps before blending, and not at all with empty masks
+        let morph = Morph::new(&dark, &bright, &[], &[], (0.5, 1.0, 1.0, 1.0));
+        let plain = morph.render();
+        let morph = morph.with_color_transfer(method);
+        let (src, dst) = color_transfer::transfer(
+            method,
+            &morph.warp_image(&dark, &[]),
+            &morph.warp_image(&bright, &[]),
+            None,
+            0.5,
+            BlendSpace::Linear,
+        );
+        assert!(*morph.render() == *morph.blend(&src, &dst));
+        assert!(*morph.render() != *plain);
+        let none = ImageBuffer::from_pixel(64, 48, image::Rgba([0u8, 0, 0, 255]));
+        assert!(*morph.with_transfer_masks(&none, &none).render() == *plain);
+
+        // Masks are warped in the same rows as the images, adding no reports and stopping with
+        // them when cancelled
+        let masked = Morph::new(&dark, &bright, &[], &[], (0.5, 1.0, 1.0, 1.0))
+            .with_color_transfer(method)
+            .with_transfer_masks(&mask, &mask);
+        let mut reports = 0;
+        let rendered = masked
+            .render_with(&mut |_| reports += 1, &CancelToken::new())
+            .unwrap();
+        assert_eq!(reports, 3 * 47 + 3);
+        let (src, dst) = masked.transfer_colors(
+            &masked.warp_image(&dark, &[]),
+            &masked.warp_image(&bright, &[]),
+            Some((
+                &masked.warp_image(&mask, &[]),
+                &masked.warp_image(&mask, &[]),
+            )),
+        );
+        assert!(*rendered == *masked.blend(&src, &dst));
+        let cancel = CancelToken::new();
+        let result = masked.render_with(
+            &mut |progress| {
+                if let Progress::Row { row: 10, .. } = progress {
+                    cancel.cancel();
+                }
+            },
+            &cancel,
+        );
+        assert_eq!(result.err(), Some(MorphError::Cancelled));
+    }
+}
+
+#[test]
+fn test_strokes() {
+    // A thick segment is one quad, with a pixel to spare on every side for the antialiased edge
+    // and ends
+    let mut segment = strokes::Strokes::new();
+    segment.segment([0.0, 0.0], [10.0, 0.0], 4.0, [1.0; 4]);
+    assert_eq!(segment.vertices().len(), 6);
+    for v in segment.vertices() {
+        assert!(v.position[0] == -1.0 || v.position[0] == 11.0);
+        assert_eq!(v.position[1].abs(), 3.0);
+        assert_eq!(v.local[0], v.position[0] - 5.0);
+        assert_eq!(v.radius, 2.0);
+        assert_eq!(v.half_length, 5.0);
+    }
+
+    // The arrow's tip is a dot, ahead of the middle of the line in its direction
+    let tip = |a: [f64; 2], b: [f64; 2]| {
+        let mut arrow = strokes::Strokes::new();
+        arrow.arrow(a, b, 6.0, 3.0, [1.0; 4]);
+        let dot: Vec<_> = arrow
+            .vertices()
+            .iter()
+            .filter(|v| v.half_length == 0.0)
+            .collect();
+        assert_eq!(dot.len(), 6);
+        dot.iter().map(|v| v.position[0]).sum::<f32>() / 6.0
+    };
+    assert_eq!(tip([0.0, 0.0], [20.0, 0.0]), 13.0);
+    assert_eq!(tip([20.0, 0.0], [0.0, 0.0]), 7.0);
+}
+
+#[test]
+fn test_align_lines() {
+    // Lines rotated by 90 degrees, doubled in size and shifted are mapped straight back
+    let from = vec![line(10.0, 10.0, 30.0, 10.0), line(10.0, 20.0, 10.0, 40.0)];
+    let to = vec![line(80.0, 40.0, 80.0, 80.0), line(60.0, 40.0, 20.0, 40.0)];
+    let transform = align::Transform::fit_lines(&from, &to).unwrap();
+    for (a, b) in from.iter().zip(to.iter()) {
+        let mapped = transform.apply_line(a);
+        for (m, b) in mapped.iter().zip(b.iter()) {
+            assert!((m.position[0] - b.position[0]).abs() < 1e-9);
+            assert!((m.position[1] - b.position[1]).abs() < 1e-9);
+        }
+    }
+
+    // A single point can't fix rotation and scale
+    let dot = vec![line(5.0, 5.0, 5.0, 5.0)];
+    assert!(align::Transform::fit_lines(&dot, &dot).is_none());
+    assert!(align::Transform::fit_lines(&[], &[]).is_none());
+    assert_eq!(
+        align::Transform::scale(2.0, 0.5).apply([3.0, 4.0]),
+        [6.0, 2.0]
+    );
 }